@@ -1,21 +1,15 @@
 //! Types used in decide.pfe.io public API.
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CondorcetTally {
-    /// totals[a][b] contains the number of votes where candidate a beat b.
-    pub totals: Vec<Vec<u64>>,
-    // Ranks[0] contains the winner(s), ranks[n] contains the winners if you
-    // remove the members of all previous ranks.
-    pub ranks: Vec<Vec<usize>>,
-}
-
-#[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct VoteItem {
-    pub candidate: usize,
-    // Lower is better.
-    pub rank: u64,
-}
+pub use decide_core::{
+    condorcet::{AuxiliaryScores, CondorcetTally, CopelandScore, PairGroup, RankedPair, VoteItem},
+    irv::{IrvRound, IrvTally},
+    robustness::{Margin, Robustness},
+    score::{ScoreItem, ScoreTally, StarRunoff},
+    stv::{StvRound, StvTally},
+    tiebreak::{TieBreak, TieBreakResult},
+    TallyMethod,
+};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -23,38 +17,203 @@ pub enum ClientStatus {
     Connected,
     InvalidRoom,
     InvalidUuid,
+    /// The client's last vote was rejected; see `ClientNotification::error`.
+    InvalidVote,
+    /// The client sent an admin command without the room's admin token.
+    PermissionDenied,
+    /// The client's last change to the candidates was rejected; see
+    /// `ClientNotification::candidate_error`.
+    InvalidCandidate,
+}
+
+/// Longest voter name accepted, in characters.
+pub const MAX_NAME_LEN: usize = 100;
+
+/// Why a submitted vote was rejected.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VoteError {
+    UnknownCandidate(usize),
+    DuplicateCandidate(usize),
+    NameTooLong,
+    ScoreTooHigh(u64),
+    /// The ballot ranks candidates in a room that scores them, or the other
+    /// way around.
+    WrongBallotType,
+}
+
+impl std::fmt::Display for VoteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownCandidate(c) => write!(f, "unknown candidate {c}"),
+            Self::DuplicateCandidate(c) => write!(f, "candidate {c} appears more than once"),
+            Self::NameTooLong => write!(f, "name longer than {MAX_NAME_LEN} characters"),
+            Self::ScoreTooHigh(score) => write!(f, "score {score} is above the maximum"),
+            Self::WrongBallotType => write!(f, "ballot type doesn't match the room's method"),
+        }
+    }
+}
+
+/// Why a change to a room's candidates was rejected.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CandidateError {
+    UnknownCandidate(usize),
+    EmptyName,
+    /// Candidates can only change while voting is open.
+    VotingClosed,
+}
+
+impl std::fmt::Display for CandidateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownCandidate(c) => write!(f, "unknown candidate {c}"),
+            Self::EmptyName => write!(f, "empty candidate name"),
+            Self::VotingClosed => write!(f, "voting is closed"),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct VotingResults {
+    /// The candidates the results refer to by index: the room's choices
+    /// without any withdrawn candidates.
+    pub choices: Vec<String>,
+    /// Method used to compute the results. Differs from the room's method if
+    /// that method could not be used, e.g. Kemeny-Young with too many choices.
+    pub method: TallyMethod,
     pub tally: CondorcetTally,
+    /// Round-by-round trace, present if the room uses instant-runoff voting.
+    pub irv: Option<IrvTally>,
+    /// Transfer rounds, present if the room uses the single transferable vote.
+    pub stv: Option<StvTally>,
+    /// Score totals, present if the room uses approval or STAR voting.
+    pub score: Option<ScoreTally>,
+    /// Borda and Copeland scores, present if the room uses ranked ballots.
+    pub auxiliary: Option<AuxiliaryScores>,
+    /// How many ballots it would take to change the winners, present if the
+    /// results were computed with ranked pairs.
+    pub robustness: Option<Robustness>,
+    /// Strict order of candidates, present if the room breaks ties. Ties are
+    /// still reported in `tally.ranks`.
+    pub tie_break: Option<TieBreakResult>,
     pub votes: Vec<UserVote>,
 }
 
+/// A finished round of voting, kept unchanged once the room starts a new
+/// round.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PastRound {
+    /// The choices as they were during the round.
+    pub choices: Vec<String>,
+    /// Results as of the end of the round, including its ballots.
+    pub results: VotingResults,
+}
+
+impl PastRound {
+    pub fn summary(&self) -> RoundSummary {
+        let winners = self.results.tally.ranks.first().into_iter().flatten();
+        RoundSummary {
+            num_votes: self.results.votes.len(),
+            winners: winners.map(|&c| self.results.choices[c].clone()).collect(),
+        }
+    }
+}
+
+/// An earlier round as sent with every update. Clients load the full results
+/// from the room's history export when they need them.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RoundSummary {
+    pub num_votes: usize,
+    /// Names of the round's winners; more than one if they tied.
+    pub winners: Vec<String>,
+}
+
+/// Standings while voting is still open.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProvisionalResults {
+    /// totals[a][b] contains the number of votes so far ranking a over b.
+    pub totals: Vec<Vec<u64>>,
+    /// Candidates currently winning under the room's method.
+    pub leaders: Vec<usize>,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct VoteView {
     pub choices: Vec<String>,
+    /// Candidates withdrawn while voting was open. They stay in `choices` so
+    /// that ballots keep their meaning, but are left out of the results.
+    pub withdrawn: Vec<usize>,
+    pub method: TallyMethod,
+    /// Highest score a ballot may give, or None if ballots are ranked.
+    pub max_score: Option<u64>,
     pub your_vote: Option<UserVote>,
     pub num_votes: usize,
     pub num_players: usize,
+    /// Whether this client may use admin commands, e.g. Tally.
+    pub is_admin: bool,
+    /// Seconds until voting closes on its own, if the room has a deadline
+    /// and is still open.
+    pub seconds_left: Option<u64>,
+    /// Voting closes once this many ballots are in.
+    pub close_after_ballots: Option<usize>,
+    /// Voting closes once every connected participant has voted.
+    pub close_when_all_voted: bool,
+    /// Present before the results if the room shows live results.
+    pub provisional: Option<ProvisionalResults>,
     pub results: Option<VotingResults>,
+    /// Earlier rounds in this room, oldest first.
+    pub history: Vec<RoundSummary>,
 }
 
 #[derive(Deserialize)]
 pub struct NewVoteForm {
     pub choices: String,
+    #[serde(default)]
+    pub method: TallyMethod,
+    /// Number of candidates to elect. Only used by multi-winner methods.
+    #[serde(default = "default_num_winners")]
+    pub num_winners: usize,
+    /// Not used by multi-winner methods.
+    #[serde(default)]
+    pub tie_break: TieBreak,
+    /// Show provisional standings to everyone while voting is open.
+    #[serde(default)]
+    pub live_results: bool,
+    /// Close voting automatically after this many minutes. 0 means voting
+    /// stays open until the admin ends it.
+    #[serde(default)]
+    pub closes_in_minutes: u64,
+    /// Close voting automatically once this many ballots are in. 0 means
+    /// there's no quorum.
+    #[serde(default)]
+    pub close_after_ballots: usize,
+    /// Close voting automatically once every connected participant has voted.
+    #[serde(default)]
+    pub close_when_all_voted: bool,
+}
+
+pub fn default_num_winners() -> usize {
+    1
 }
 
 #[derive(Deserialize)]
 pub struct VoteWebsocketQueryParams {
     /// Unique per client per room. Used to allow reconnections.
     pub id: String,
+    /// Admin token issued when the room was created, if the client has it.
+    #[serde(default)]
+    pub admin: Option<String>,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub struct UserVote {
     pub name: String,
+    /// Ranked ballot, used by rooms without a max score.
     pub selections: Vec<VoteItem>,
+    /// Scored ballot, used by rooms with a max score.
+    #[serde(default)]
+    pub scores: Vec<ScoreItem>,
 }
 
 /// Data received from a client over websocket.
@@ -62,7 +221,24 @@ pub struct UserVote {
 #[serde(rename_all = "snake_case")]
 pub enum Command {
     Vote(UserVote),
+    /// End voting. Admin only.
     Tally,
+    /// Let voting continue after it ended, keeping the ballots. Admin only.
+    Reopen,
+    /// Add this round to the room's history and start voting again with no
+    /// ballots. Admin only.
+    NewRound,
+    /// Add a candidate while voting is open. Existing ballots leave it
+    /// unranked. Admin only.
+    AddCandidate(String),
+    /// Admin only.
+    RenameCandidate {
+        candidate: usize,
+        name: String,
+    },
+    /// Take a candidate out of the running while voting is open. Ballots
+    /// keep it, but it's removed from them before tallying. Admin only.
+    WithdrawCandidate(usize),
 }
 
 impl Command {
@@ -70,6 +246,11 @@ impl Command {
         match self {
             Self::Vote(_) => "vote",
             Self::Tally => "tally",
+            Self::Reopen => "reopen",
+            Self::NewRound => "new_round",
+            Self::AddCandidate(_) => "add_candidate",
+            Self::RenameCandidate { .. } => "rename_candidate",
+            Self::WithdrawCandidate(_) => "withdraw_candidate",
         }
     }
 }
@@ -79,4 +260,10 @@ impl Command {
 pub struct ClientNotification {
     pub status: ClientStatus,
     pub vote: Option<VoteView>,
+    /// Present if the status is `InvalidVote`.
+    #[serde(default)]
+    pub error: Option<VoteError>,
+    /// Present if the status is `InvalidCandidate`.
+    #[serde(default)]
+    pub candidate_error: Option<CandidateError>,
 }
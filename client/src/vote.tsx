@@ -1,4 +1,5 @@
 import { Component, createRef, Fragment } from 'preact';
+import { useEffect, useState } from 'preact/hooks';
 import Cookies from 'js-cookie';
 import { v4 as uuidv4 } from 'uuid';
 import { route } from 'preact-router';
@@ -16,7 +17,23 @@ function get_vote_uuid() {
     return uuid;
 }
 
+// The admin token arrives in the URL fragment when the room is created. Keep
+// it out of the URL so the link voters are sent doesn't carry it.
+function get_admin_token(room: string): string | null {
+    const key = `VOTE_ADMIN_${room}`;
+    const match = window.location.hash.match(/^#admin=(\w+)$/);
+    if (match) {
+        localStorage.setItem(key, match[1]);
+        history.replaceState(null, "", window.location.pathname + window.location.search);
+    }
+    return localStorage.getItem(key);
+}
+
 function describe_vote(choices: string[], vote: UserVote) {
+    if (vote.scores && vote.scores.length > 0) {
+        const scores = vote.scores.map(item => `${choices[item.candidate]} ${item.score}`);
+        return `${vote.name}: ${scores.join(", ")}`;
+    }
     let s = `${vote.name}: `;
     for (let j = 0; j < vote.selections.length; j++) {
         let vi = vote.selections[j];
@@ -371,27 +388,174 @@ class Choices extends Component<ChoicesProps, ChoicesState> {
     }
 }
 
+type ScoreItem = {
+    candidate: number,
+    score: number,
+}
+
+type ScoreBallotProps = {
+    choices: string[],
+    // Display order of the candidates.
+    order: number[],
+    max_score: number,
+    initial_scores: ScoreItem[],
+};
+
+type ScoreBallotState = {
+    scores: number[],
+};
+
+class ScoreBallot extends Component<ScoreBallotProps, ScoreBallotState> {
+    constructor(props: ScoreBallotProps) {
+        super();
+        let scores = Array(props.choices.length).fill(0);
+        for (let item of props.initial_scores) {
+            scores[item.candidate] = item.score;
+        }
+        this.state = { scores };
+    }
+
+    setScore(candidate: number, score: number) {
+        let scores = [...this.state.scores];
+        scores[candidate] = score;
+        this.setState({ scores });
+    }
+
+    getScores() {
+        return this.state.scores.map((score, candidate) => ({ candidate, score }));
+    }
+
+    render(props: ScoreBallotProps, state: ScoreBallotState) {
+        const rows = props.order.map(candidate => {
+            const id = `score-${candidate}`;
+            let input;
+            if (props.max_score === 1) {
+                input = <input
+                    type="checkbox"
+                    id={id}
+                    checked={state.scores[candidate] === 1}
+                    onChange={(e) => this.setScore(candidate, (e.target as HTMLInputElement).checked ? 1 : 0)}
+                />;
+            } else {
+                const options = Array(props.max_score + 1).fill(null).map((_, score) =>
+                    <option key={score} value={score}>{score}</option>
+                );
+                input = <select
+                    id={id}
+                    value={state.scores[candidate]}
+                    onChange={(e) => this.setScore(candidate, Number((e.target as HTMLSelectElement).value))}
+                >{options}</select>;
+            }
+            return <tr key={candidate}>
+                <td><label for={id}>{props.choices[candidate]}</label></td>
+                <td>{input}</td>
+            </tr>;
+        });
+        return <table class="ballot-table" aria-label="Ballot">
+            <tbody>{rows}</tbody>
+        </table>;
+    }
+}
+
 type UserVote = {
     selections: VoteItem[],
+    scores?: ScoreItem[],
     name: string,
 }
 
+type PairGroup = {
+    strength: number,
+    margin: number,
+    pairs: { winner: number, loser: number, locked: boolean }[],
+}
+
 type Tally = {
     totals: number[][],
     ranks: number[][],
+    pair_groups: PairGroup[],
+    smith_set: number[],
+    schwartz_set: number[],
+    condorcet_winner: number | null,
+    condorcet_cycle: boolean,
+}
+
+type Round = {
+    counts: (number | null)[],
+    exhausted: number,
+    elected?: number[],
+    eliminated: number[],
+}
+
+type StarRunoff = {
+    finalists: [number, number],
+    preferred: [number, number],
+    no_preference: number,
+}
+
+type TieBreakResult = {
+    method: string,
+    seed: number,
+    order: number[] | null,
+    rps_room: string | null,
+}
+
+type Margin = {
+    ballots: number,
+    challenger: number,
 }
 
 type Results = {
+    // The candidates the results refer to, without any withdrawn ones.
+    choices: string[],
+    method: string,
     votes: UserVote[]
     tally: Tally,
+    irv: { rounds: Round[] } | null,
+    stv: { quota: number, elected: number[], rounds: Round[] } | null,
+    score: { totals: number[], runoff: StarRunoff | null } | null,
+    auxiliary: {
+        borda: number[],
+        copeland: { wins: number, losses: number, ties: number }[],
+    } | null,
+    robustness: { ballots_to_add: Margin | null, ballots_to_change: Margin | null } | null,
+    tie_break: TieBreakResult | null,
 }
 
-function VoteResults({ choices, results }: { choices: string[], results: Results }) {
-    const votes = results.votes.map((v, i) => <li key={i}>{describe_vote(choices, v)}</li>);
-    votes.sort();
+type PastRound = {
+    choices: string[]
+    results: Results
+}
+
+type RoundSummary = {
+    num_votes: number
+    winners: string[]
+}
+
+function RoundsTable({ choices, rounds }: { choices: string[], rounds: Round[] }) {
+    const names = (cs: number[]) => cs.map(c => choices[c]).join(", ");
+    const thead = <thead><tr>
+        <th scope="col">Round</th>
+        {choices.map((c, i) => <th key={i} scope="col">{c}</th>)}
+        <th scope="col">Exhausted</th>
+        <th scope="col">Elected</th>
+        <th scope="col">Eliminated</th>
+    </tr></thead>;
+    const trows = rounds.map((round, i) => <tr key={i}>
+        <th scope="row">{i + 1}</th>
+        {round.counts.map((count, c) => <td key={c}>{count === null ? "-" : +count.toFixed(2)}</td>)}
+        <td>{+round.exhausted.toFixed(2)}</td>
+        <td>{names(round.elected || [])}</td>
+        <td>{names(round.eliminated)}</td>
+    </tr>);
+    return <table role="grid" aria-label="Counting rounds">
+        {thead}
+        <tbody>{trows}</tbody>
+    </table>
+}
+
+function PairwiseMatrix({ choices, totals }: { choices: string[], totals: number[][] }) {
     const tchoices = choices.map((c, i) => <th key={i} scope="row">{c}</th>);
     const thead = <thead><tr><th key="head" scope="col" />{tchoices}</tr></thead>;
-    const totals = results.tally.totals;
     const trows = totals.map((_, i) => {
         const tds = totals[i].map((val, j) => {
             const symbol = (i === j) ? "-" : (val > totals[j][i]) ? <mark>{val}</mark> : val.toString();
@@ -399,25 +563,166 @@ function VoteResults({ choices, results }: { choices: string[], results: Results
         });
         return <tr key={i}><th key="head" scope="row">{choices[i]}</th>{tds}</tr>
     });
+    return <table role="grid" aria-label="Vote comparison matrix">
+        {thead}
+        <tbody>{trows}</tbody>
+    </table>
+}
+
+function RoundHistory({ room, method, history }: { room: string, method: string, history: RoundSummary[] }) {
+    // Updates only summarize earlier rounds; their results are loaded once a
+    // round is opened.
+    const [rounds, setRounds] = useState<PastRound[]>([]);
+    const load = () => {
+        if (rounds.length < history.length) {
+            fetch(`/api/vote/${room}/history`)
+                .then(response => response.json())
+                .then(setRounds)
+                .catch(e => console.log(e));
+        }
+    };
+    return <section>
+        <h2>Earlier Rounds</h2>
+        {history.map((round, i) => <details onToggle={load}>
+            <summary>Round {i + 1}: {round.num_votes} ballots, won by {round.winners.join(" AND ") || "nobody"}</summary>
+            {rounds[i]
+                ? <VoteResults choices={rounds[i].results.choices} method={method} results={rounds[i].results} />
+                : <p role="status">Loading...</p>}
+        </details>)}
+        <p><a href={`/api/vote/${room}/history`} download={`vote-${room}-history.json`}>Download earlier rounds as JSON</a></p>
+    </section>
+}
+
+function format_duration(seconds: number): string {
+    const hours = Math.floor(seconds / 3600);
+    const minutes = Math.floor(seconds / 60) % 60;
+    const pad = (n: number) => n.toString().padStart(2, "0");
+    return `${hours > 0 ? `${hours}:${pad(minutes)}` : minutes}:${pad(seconds % 60)}`;
+}
+
+// Counts down locally between server updates. `closes_at` is in client time.
+function Countdown({ closes_at }: { closes_at: number }) {
+    const [now, setNow] = useState(Date.now());
+    useEffect(() => {
+        const interval = setInterval(() => setNow(Date.now()), 1000);
+        return () => clearInterval(interval);
+    }, []);
+    const seconds_left = Math.max(0, Math.ceil((closes_at - now) / 1000));
+    return <p role="timer">Voting closes automatically in {format_duration(seconds_left)}.</p>
+}
+
+function ProvisionalResults({ choices, provisional }: { choices: string[], provisional: Provisional }) {
+    const leaders = provisional.leaders.map(c => choices[c]);
+    return <article>
+        <header>
+            <h2>Provisional results</h2>
+        </header>
+        <p role="status">
+            {leaders.length === 0 ? "Nobody is leading yet." : <Fragment>
+                Currently {leaders.length > 1 ? "tied for the lead" : "leading"}: <strong>{leaders.join(" AND ")}</strong>
+            </Fragment>}
+        </p>
+        <details>
+            <summary>See the head-to-head votes so far</summary>
+            <PairwiseMatrix choices={choices} totals={provisional.totals} />
+        </details>
+    </article>
+}
+
+function VoteResults({ choices, method, results }: { choices: string[], method: string, results: Results }) {
+    const votes = results.votes.map((v, i) => <li key={i}>{describe_vote(choices, v)}</li>);
+    votes.sort();
     const ranks = results.tally.ranks.map(
         (rank, i) => <li key={i}>{rank.map(c => choices[c]).join(" AND ")}</li>
     );
+    const pairs = results.tally.pair_groups.flatMap(group => group.pairs.map(pair =>
+        <li key={`${pair.winner}-${pair.loser}`}>
+            {choices[pair.winner]} beats {choices[pair.loser]} ({group.strength} votes, margin {group.margin}): {
+                pair.locked ? "locked" : "skipped, as it would create a cycle"
+            }
+        </li>
+    ));
     const winners = (results.tally.ranks[0] || []).map(i => choices[i]);
     let winner_desc = (winners.length > 1) ? "winners are" : "winner is";
+    const tally = results.tally;
+    let condorcet_desc;
+    if (tally.condorcet_winner !== null) {
+        condorcet_desc = `${choices[tally.condorcet_winner]} beats every other choice head-to-head.`;
+    } else if (tally.condorcet_cycle) {
+        condorcet_desc = "No choice beats every other head-to-head: the majority preferences form a cycle, so some had to be overruled.";
+    } else {
+        condorcet_desc = "No choice beats every other head-to-head.";
+    }
     return <article>
         <header role="banner">
             <h2>The results are in! The {winner_desc}: <strong>{winners.join(" AND ")}</strong></h2>
         </header>
+        {method !== results.method && <p role="status">
+            This election has too many choices for the chosen tally method, so ranked pairs was used instead.
+        </p>}
+        <p>{condorcet_desc}</p>
+        {winners.length > 1 && results.tie_break && results.tie_break.order && <p role="status">
+            The tie was broken {results.tie_break.method === "lottery" ? "by drawing lots" : "using a random voter's ballot"} (seed {results.tie_break.seed}):
+            the winner is <strong>{choices[results.tie_break.order[0]]}</strong>.
+        </p>}
+        {results.tie_break && results.tie_break.rps_room && <p role="status">
+            Break the tie with <a href={`/rps/${results.tie_break.rps_room}`}>rock paper scissors</a>!
+        </p>}
         <details>
             <summary>See detailed results</summary>
+            <p>Smith set: {tally.smith_set.map(c => choices[c]).join(", ")}</p>
+            <p>Schwartz set: {tally.schwartz_set.map(c => choices[c]).join(", ")}</p>
             <p>The votes are:</p>
             <ul>
                 {votes}
             </ul>
-            <table role="grid" aria-label="Vote comparison matrix">
-                {thead}
-                <tbody>{trows}</tbody>
-            </table>
+            <PairwiseMatrix choices={choices} totals={tally.totals} />
+            {results.irv && <Fragment>
+                <p>The instant-runoff rounds are:</p>
+                <RoundsTable choices={choices} rounds={results.irv.rounds} />
+            </Fragment>}
+            {results.stv && <Fragment>
+                <p>The transfer rounds, with a quota of {results.stv.quota} votes, are:</p>
+                <RoundsTable choices={choices} rounds={results.stv.rounds} />
+            </Fragment>}
+            {pairs.length > 0 && <Fragment>
+                <p>Ranked pairs locks in each pairwise win, strongest first, unless it contradicts a stronger one:</p>
+                <ol>
+                    {pairs}
+                </ol>
+            </Fragment>}
+            {results.score && <Fragment>
+                <p>The total scores are:</p>
+                <ul>
+                    {results.score.totals.map((total, c) => <li key={c}>{choices[c]}: {total}</li>)}
+                </ul>
+            </Fragment>}
+            {results.score && results.score.runoff && <p>
+                In the runoff, {results.score.runoff.preferred[0]} voters preferred {choices[results.score.runoff.finalists[0]]}, {
+                results.score.runoff.preferred[1]} preferred {choices[results.score.runoff.finalists[1]]}, and {
+                results.score.runoff.no_preference} had no preference.
+            </p>}
+            {results.auxiliary && <Fragment>
+                <p>For comparison, the scores under simpler methods are:</p>
+                <table role="grid" aria-label="Borda and Copeland scores">
+                    <thead><tr>
+                        <th scope="col" />
+                        <th scope="col">Borda count</th>
+                        <th scope="col">Head-to-head wins/losses/ties</th>
+                    </tr></thead>
+                    <tbody>{choices.map((c, i) => <tr key={i}>
+                        <th scope="row">{c}</th>
+                        <td>{results.auxiliary.borda[i]}</td>
+                        <td>{results.auxiliary.copeland[i].wins}/{results.auxiliary.copeland[i].losses}/{results.auxiliary.copeland[i].ties}</td>
+                    </tr>)}</tbody>
+                </table>
+            </Fragment>}
+            {results.robustness && results.robustness.ballots_to_add && <p>
+                The winners would change with {results.robustness.ballots_to_add.ballots} more vote(s) for {
+                choices[results.robustness.ballots_to_add.challenger]}{results.robustness.ballots_to_change && <Fragment>, or if {
+                results.robustness.ballots_to_change.ballots} voter(s) switched to {
+                choices[results.robustness.ballots_to_change.challenger]}</Fragment>}.
+            </p>}
             <p>The full ranks are:</p>
             <ol>
                 {ranks}
@@ -426,16 +731,90 @@ function VoteResults({ choices, results }: { choices: string[], results: Results
     </article>
 }
 
+type Provisional = {
+    totals: number[][]
+    leaders: number[]
+}
+
 type VoteProps = {
     room: string
 }
 
 type VoteView = {
     choices: string[]
+    withdrawn: number[]
+    method: string
+    max_score: number | null
     your_vote: UserVote | null
     num_votes: number
     num_players: number
+    is_admin: boolean
+    seconds_left: number | null
+    close_after_ballots: number | null
+    close_when_all_voted: boolean
+    provisional: Provisional | null
     results: Results | null
+    history: RoundSummary[]
+}
+
+type VoteError =
+    { unknown_candidate: number } | { duplicate_candidate: number } | { score_too_high: number } | "name_too_long"
+    | "wrong_ballot_type";
+
+const MAX_NAME_LEN = 100;
+
+function describe_vote_error(error: VoteError): string {
+    if (error === "name_too_long") {
+        return `Your name must be at most ${MAX_NAME_LEN} characters.`;
+    } else if (error === "wrong_ballot_type") {
+        return "Your ballot doesn't match how this election is counted. Try refreshing.";
+    } else if ("unknown_candidate" in error) {
+        return "Your ballot names a choice that isn't in this election.";
+    } else if ("duplicate_candidate" in error) {
+        return "Your ballot names the same choice more than once.";
+    } else {
+        return `A score of ${error.score_too_high} is too high.`;
+    }
+}
+
+type CandidateError = { unknown_candidate: number } | "empty_name" | "voting_closed";
+
+function describe_candidate_error(error: CandidateError): string {
+    if (error === "empty_name") {
+        return "Choices need a name.";
+    } else if (error === "voting_closed") {
+        return "Choices can only change while voting is open.";
+    } else {
+        return "That choice isn't in this election.";
+    }
+}
+
+// Lets the admin add, rename and withdraw choices while voting is open.
+function CandidateEditor({ choices, withdrawn, send }: { choices: string[], withdrawn: number[], send: (command: object) => void }) {
+    const [new_name, setNewName] = useState("");
+    const add = () => {
+        send({ add_candidate: new_name });
+        setNewName("");
+    };
+    const rename = (candidate: number) => {
+        const name = prompt("New name for this choice:", choices[candidate]);
+        if (name !== null) {
+            send({ rename_candidate: { candidate, name } });
+        }
+    };
+    return <details>
+        <summary>Edit the choices</summary>
+        <ul>
+            {choices.map((choice, c) => withdrawn.includes(c) ? null : <li key={c}>
+                {choice} <button onClick={() => rename(c)}>Rename</button> <button onClick={() => send({ withdraw_candidate: c })}>Withdraw</button>
+            </li>)}
+        </ul>
+        <p>
+            <label for="new_candidate">New choice:</label> <input id="new_candidate" value={new_name}
+                onInput={e => setNewName((e.target as HTMLInputElement).value)} /> <button onClick={add}>Add</button>
+        </p>
+        <p>Ballots that were already submitted leave new choices unranked. Withdrawn choices are left out of the results.</p>
+    </details>
 }
 
 type VoteState = {
@@ -443,10 +822,16 @@ type VoteState = {
     status: string
     voter_name: string
     vote: VoteView | null
+    error: VoteError | null
+    candidate_error: CandidateError | null
+    // Whether the server refused the last admin command.
+    permission_denied: boolean
+    // When voting closes, from the last update's seconds_left, in client time.
+    closes_at: number | null
 }
 
 export class Vote extends Component<VoteProps, VoteState> {
-    state = { room: null, status: "connecting", voter_name: "", vote: null };
+    state = { room: null, status: "connecting", voter_name: "", vote: null, error: null, candidate_error: null, permission_denied: false, closes_at: null };
     ws: WebSocket | null = null;
     choices_component = createRef();
     initial_vote: UserVote | null = null;
@@ -469,6 +854,47 @@ export class Vote extends Component<VoteProps, VoteState> {
                     <form action="/api/start_vote" method="post">
                         <p><label for="choices">Enter the choices up for vote, one per line:</label></p>
                         <p><textarea name="choices" id="choices" required aria-required="true" /></p>
+                        <p>
+                            <label for="method">Tally method:</label>
+                            <select name="method" id="method">
+                                <option value="ranked_pairs">Ranked pairs</option>
+                                <option value="schulze">Schulze</option>
+                                <option value="kemeny_young">Kemeny-Young (at most 8 choices)</option>
+                                <option value="instant_runoff">Instant-runoff</option>
+                                <option value="single_transferable_vote">Single transferable vote</option>
+                                <option value="approval">Approval</option>
+                                <option value="star">STAR (score then automatic runoff)</option>
+                            </select>
+                        </p>
+                        <p>
+                            <label for="tie_break">Break ties by:</label>
+                            <select name="tie_break" id="tie_break">
+                                <option value="none">Don't break ties</option>
+                                <option value="random_ballot">Following a random voter's ballot</option>
+                                <option value="lottery">Drawing lots</option>
+                                <option value="rock_paper_scissors">Rock paper scissors (drawing lots for ties of three or more)</option>
+                            </select>
+                        </p>
+                        <p>
+                            <label for="num_winners">Number of winners (single transferable vote only):</label>
+                            <input type="number" name="num_winners" id="num_winners" min="1" value="1" />
+                        </p>
+                        <p>
+                            <input type="checkbox" name="live_results" id="live_results" value="true" />
+                            <label for="live_results">Show provisional results while voting is open</label>
+                        </p>
+                        <p>
+                            <label for="closes_in_minutes">Close voting automatically after this many minutes (0 to close it yourself):</label>
+                            <input type="number" name="closes_in_minutes" id="closes_in_minutes" min="0" value="0" />
+                        </p>
+                        <p>
+                            <label for="close_after_ballots">Close voting automatically once this many ballots are in (0 for no limit):</label>
+                            <input type="number" name="close_after_ballots" id="close_after_ballots" min="0" value="0" />
+                        </p>
+                        <p>
+                            <input type="checkbox" name="close_when_all_voted" id="close_when_all_voted" value="true" />
+                            <label for="close_when_all_voted">Close voting automatically once everyone connected has voted</label>
+                        </p>
                         <input type="submit" value="Start Vote" />
                     </form>
                 </main>
@@ -478,7 +904,9 @@ export class Vote extends Component<VoteProps, VoteState> {
         if (state.room !== props.room) {
             // The client connected to a new room. Perform initial setup.
             state.room = props.room;
-            this.ws = make_websocket(`/api/vote/${state.room}?id=${get_vote_uuid()}`);
+            const admin_token = get_admin_token(state.room);
+            const admin_param = admin_token ? `&admin=${encodeURIComponent(admin_token)}` : "";
+            this.ws = make_websocket(`/api/vote/${state.room}?id=${get_vote_uuid()}${admin_param}`);
             this.ws.onclose = evt => {
                 console.log("Websocket disconnected!");
                 console.log(evt);
@@ -486,7 +914,18 @@ export class Vote extends Component<VoteProps, VoteState> {
             };
             this.ws.onmessage = msg => {
                 let new_state = JSON.parse(msg.data);
-                this.setState(new_state)
+                if (new_state.status === "invalid_vote") {
+                    // Keep showing the room; only this client's vote was rejected.
+                    this.setState({ error: new_state.error });
+                } else if (new_state.status === "invalid_candidate") {
+                    this.setState({ candidate_error: new_state.candidate_error });
+                } else if (new_state.status === "permission_denied") {
+                    this.setState({ permission_denied: true });
+                } else {
+                    const seconds_left = new_state.vote && new_state.vote.seconds_left;
+                    const closes_at = seconds_left != null ? Date.now() + seconds_left * 1000 : null;
+                    this.setState({ ...new_state, closes_at, candidate_error: null, permission_denied: false });
+                }
             };
         }
         if (state.status === "connecting") {
@@ -512,15 +951,24 @@ export class Vote extends Component<VoteProps, VoteState> {
 
         const submit = () => {
             const choices_component = this.choices_component.current;
-            let items = choices_component.getSelections();
-            this.ws.send(JSON.stringify({ vote: { name: this.state.voter_name, selections: items } }))
+            let vote;
+            if (this.state.vote.max_score !== null) {
+                vote = { name: this.state.voter_name, selections: [], scores: choices_component.getScores() };
+            } else {
+                vote = { name: this.state.voter_name, selections: choices_component.getSelections() };
+            }
+            this.setState({ error: null });
+            this.ws.send(JSON.stringify({ vote }))
         };
 
         const tally = () => this.ws.send(JSON.stringify({ tally: null }));
+        const reopen = () => this.ws.send(JSON.stringify({ reopen: null }));
+        const new_round = () => this.ws.send(JSON.stringify({ new_round: null }));
+        const send = (command: object) => this.ws.send(JSON.stringify(command));
 
         let results = null;
         if (state.vote.results) {
-            results = <VoteResults choices={state.vote.choices} results={state.vote.results} />
+            results = <VoteResults choices={state.vote.results.choices} method={state.vote.method} results={state.vote.results} />
         }
 
         const submit_text = (state.vote.your_vote) ? "Resubmit Your Vote" : "Submit Your Vote";
@@ -539,22 +987,62 @@ export class Vote extends Component<VoteProps, VoteState> {
                     );
                 this.initial_vote = {
                     name: "???",
-                    selections: initial_selections
+                    selections: initial_selections,
+                    scores: [],
                 }
                 this.setState({ voter_name: this.initial_vote.name })
             }
         }
 
-        const ballot_section = (
-            <Fragment>
+        // Score ballots have no selections, but list every candidate.
+        const { withdrawn } = state.vote;
+        const in_running = (c: number) => !withdrawn.includes(c);
+        let order = (this.initial_vote.selections.length > 0)
+            ? this.initial_vote.selections.map(item => item.candidate)
+            : (this.initial_vote.scores || []).map(item => item.candidate);
+        // Choices added since the ballot was filled in go last.
+        const added = state.vote.choices.map((_, c) => c).filter(c => !order.includes(c));
+        order = order.concat(added).filter(in_running);
+        const last_rank = Math.max(-1, ...this.initial_vote.selections.map(item => item.rank));
+        const initial_ranks = this.initial_vote.selections
+            .concat(added.map(candidate => ({ candidate, rank: last_rank + 1 })))
+            .filter(item => in_running(item.candidate));
+        // Start the ballot over when the choices change.
+        const ballot_key = `${state.vote.choices.length}/${withdrawn.join(",")}`;
+        let ballot;
+        if (state.vote.max_score === 1) {
+            ballot = <Fragment>
+                <p>Check every choice you approve of.</p>
+                <div role="region" aria-label="Voting ballot">
+                    <ScoreBallot key={ballot_key} ref={this.choices_component} choices={state.vote.choices} max_score={1}
+                        order={order} initial_scores={this.initial_vote.scores || []} />
+                </div>
+            </Fragment>;
+        } else if (state.vote.max_score !== null) {
+            ballot = <Fragment>
+                <p>Score each choice from 0 to {state.vote.max_score}. {state.vote.max_score} is best.</p>
+                <div role="region" aria-label="Voting ballot">
+                    <ScoreBallot key={ballot_key} ref={this.choices_component} choices={state.vote.choices} max_score={state.vote.max_score}
+                        order={order} initial_scores={this.initial_vote.scores || []} />
+                </div>
+            </Fragment>;
+        } else {
+            ballot = <Fragment>
                 <p>Click or drag or use tab/arrow keys to edit your ballot. Rank 1 is best.</p>
                 <div role="region" aria-label="Voting ballot">
-                    <Choices ref={this.choices_component} choices={state.vote.choices} initial_ranks={this.initial_vote.selections} />
+                    <Choices key={ballot_key} ref={this.choices_component} choices={state.vote.choices} initial_ranks={initial_ranks} />
                 </div>
+            </Fragment>;
+        }
+
+        const ballot_section = (
+            <Fragment>
+                {ballot}
                 <p>
                     <label for="voter_name">Voter name (optional):</label>
-                    <input id="voter_name" value={state.voter_name} onInput={on_input} />
+                    <input id="voter_name" value={state.voter_name} onInput={on_input} maxLength={MAX_NAME_LEN} />
                 </p>
+                {state.error && <p class="notice" role="alert">{describe_vote_error(state.error)}</p>}
                 <p><button onClick={submit}>{submit_text}</button></p>
             </Fragment>
         );
@@ -565,9 +1053,25 @@ export class Vote extends Component<VoteProps, VoteState> {
                     {state.vote.num_players <= 1 && <p class="notice" role="status"> Send this URL to all voters:<br /><CopyLink /> </p>}
                     {!state.vote.results && ballot_section}
                     {submitted_section}
-                    {!state.vote.results && <p><button onClick={tally}>End Voting and Show the Results</button></p>}
+                    {!state.vote.results && state.vote.is_admin && <p><button onClick={tally}>End Voting and Show the Results</button></p>}
+                    {!state.vote.results && state.vote.is_admin &&
+                        <CandidateEditor choices={state.vote.choices} withdrawn={state.vote.withdrawn} send={send} />}
+                    {state.candidate_error && <p class="notice" role="alert">{describe_candidate_error(state.candidate_error)}</p>}
+                    {state.permission_denied && <p class="notice" role="alert">Only the vote's creator can do that.</p>}
+                    {!state.vote.results && state.closes_at !== null && <Countdown closes_at={state.closes_at} />}
+                    {!state.vote.results && state.vote.close_after_ballots !== null &&
+                        <p>Voting closes automatically once {state.vote.close_after_ballots} ballots are in.</p>}
+                    {!state.vote.results && state.vote.close_when_all_voted &&
+                        <p>Voting closes automatically once everyone here has voted.</p>}
                     <p role="status">{state.vote.num_votes}/{state.vote.num_players} voters have submitted ballots.</p>
+                    {!state.vote.results && state.vote.provisional &&
+                        <ProvisionalResults choices={state.vote.choices} provisional={state.vote.provisional} />}
                     {results}
+                    {state.vote.results && state.vote.is_admin && <p>
+                        <button onClick={reopen}>Reopen Voting</button> <button onClick={new_round}>Start a New Round</button>
+                    </p>}
+                    {state.vote.history.length > 0 &&
+                        <RoundHistory room={state.room} method={state.vote.method} history={state.vote.history} />}
                 </main>
                 <footer>
                     <p>This election will be <strong>deleted</strong> after 24 hours of inactivity.</p>
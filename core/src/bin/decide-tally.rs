@@ -0,0 +1,367 @@
+//! Tally ballots from a file or stdin with the same code as decide.pfe.io.
+//!
+//! Ballots are read as ABIF unless another format is given or the file
+//! extension names one. In ABIF each line holds one ballot, optionally
+//! preceded by a count, e.g. `3: Alice > Bob = Carol`.
+use std::io::{Read, Write};
+
+use decide_core::{
+    condorcet::{kemeny_young, pairwise_totals, ranked_pairs, schulze, CondorcetTally},
+    formats::Format,
+    irv::instant_runoff,
+    stv::single_transferable_vote,
+    TallyMethod,
+};
+
+const USAGE: &str = "Usage: decide-tally [--method METHOD] [--winners N] [--format FORMAT]
+                    [--convert FORMAT] [FILE]
+
+Methods: ranked_pairs (default), schulze, kemeny_young, instant_runoff,
+single_transferable_vote.
+Formats: abif (default), blt, preflib (.soc, .soi, .toc, .toi).
+Reads ballots from stdin if FILE is absent or -. With --convert, writes the
+ballots to stdout in FORMAT instead of tallying them.";
+
+fn parse_method(name: &str) -> Option<TallyMethod> {
+    match name {
+        "ranked_pairs" => Some(TallyMethod::RankedPairs),
+        "schulze" => Some(TallyMethod::Schulze),
+        "kemeny_young" => Some(TallyMethod::KemenyYoung),
+        "instant_runoff" => Some(TallyMethod::InstantRunoff),
+        "single_transferable_vote" => Some(TallyMethod::SingleTransferableVote),
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+struct Args {
+    /// Print the usage instead of tallying.
+    help: bool,
+    method: TallyMethod,
+    /// Defaults to the number of seats in the ballot file.
+    num_winners: Option<usize>,
+    format: Option<Format>,
+    convert: Option<Format>,
+    path: Option<String>,
+}
+
+fn parse_format(name: Option<String>) -> Result<Format, String> {
+    let name = name.ok_or("--format and --convert need a value")?;
+    Format::from_name(&name).ok_or(format!("Unknown format: {name}"))
+}
+
+fn parse_args(mut iter: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut args = Args {
+        help: false,
+        method: TallyMethod::RankedPairs,
+        num_winners: None,
+        format: None,
+        convert: None,
+        path: None,
+    };
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-h" | "--help" => args.help = true,
+            "-m" | "--method" => {
+                let method = iter.next().ok_or("--method needs a value")?;
+                args.method = parse_method(&method).ok_or(format!("Unknown method: {method}"))?;
+            }
+            "-w" | "--winners" => {
+                let num_winners = iter.next().ok_or("--winners needs a value")?;
+                args.num_winners = Some(
+                    num_winners
+                        .parse()
+                        .map_err(|e| format!("Invalid number of winners {num_winners:?}: {e}"))?,
+                );
+            }
+            "-f" | "--format" => args.format = Some(parse_format(iter.next())?),
+            "-c" | "--convert" => args.convert = Some(parse_format(iter.next())?),
+            "-" => args.path = None,
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: {arg}\n\n{USAGE}")),
+            _ => args.path = Some(arg),
+        }
+    }
+    Ok(args)
+}
+
+fn names(candidates: &[String], group: &[usize]) -> String {
+    if group.is_empty() {
+        return "none".into();
+    }
+    group
+        .iter()
+        .map(|&c| candidates[c].as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn print_ranks(
+    out: &mut impl Write,
+    candidates: &[String],
+    ranks: &[Vec<usize>],
+) -> std::io::Result<()> {
+    writeln!(out, "Ranking:")?;
+    for (i, rank) in ranks.iter().enumerate() {
+        let rank = rank
+            .iter()
+            .map(|&c| candidates[c].as_str())
+            .collect::<Vec<_>>()
+            .join(" = ");
+        writeln!(out, "  {}. {rank}", i + 1)?;
+    }
+    Ok(())
+}
+
+fn print_totals(
+    out: &mut impl Write,
+    candidates: &[String],
+    totals: &[Vec<u64>],
+) -> std::io::Result<()> {
+    writeln!(out, "Pairwise matrix (votes preferring row over column):")?;
+    let width = candidates.iter().map(|c| c.len()).max().unwrap_or(0).max(5);
+    write!(out, "  {:width$}", "")?;
+    for c in candidates {
+        write!(out, " {c:>width$}")?;
+    }
+    writeln!(out)?;
+    for (a, row) in totals.iter().enumerate() {
+        write!(out, "  {:width$}", candidates[a])?;
+        for (b, total) in row.iter().enumerate() {
+            if a == b {
+                write!(out, " {:>width$}", "-")?;
+            } else {
+                write!(out, " {total:>width$}")?;
+            }
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn print_analysis(
+    out: &mut impl Write,
+    candidates: &[String],
+    tally: &CondorcetTally,
+) -> std::io::Result<()> {
+    let analysis = &tally.analysis;
+    match analysis.condorcet_winner {
+        Some(winner) => writeln!(
+            out,
+            "  {} beats every other candidate head-to-head.",
+            candidates[winner]
+        )?,
+        None if analysis.condorcet_cycle => writeln!(
+            out,
+            "  No candidate beats every other head-to-head, \
+             and the majority preferences contain a cycle."
+        )?,
+        None => writeln!(out, "  No candidate beats every other head-to-head.")?,
+    }
+    writeln!(
+        out,
+        "  Smith set: {}",
+        names(candidates, &analysis.smith_set)
+    )?;
+    writeln!(
+        out,
+        "  Schwartz set: {}",
+        names(candidates, &analysis.schwartz_set)
+    )?;
+    for group in tally.pair_groups.iter() {
+        for pair in group.pairs.iter() {
+            let verdict = if pair.locked {
+                "locked"
+            } else {
+                "skipped, would create a cycle"
+            };
+            writeln!(
+                out,
+                "  {} > {} (strength {}, margin {}): {verdict}",
+                candidates[pair.winner], candidates[pair.loser], group.strength, group.margin
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn print_counts(candidates: &[String], counts: &[Option<f64>], exhausted: f64) -> String {
+    counts
+        .iter()
+        .enumerate()
+        .filter_map(|(c, count)| count.map(|count| format!("{} {count:.2}", candidates[c])))
+        .chain(std::iter::once(format!("exhausted {exhausted:.2}")))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn tally(args: &Args, input: &str, out: &mut impl Write) -> Result<(), Box<dyn std::error::Error>> {
+    let format = args
+        .format
+        .or_else(|| args.path.as_deref().and_then(Format::from_path))
+        .unwrap_or(Format::Abif);
+    let election = format.parse(input)?;
+    if let Some(output_format) = args.convert {
+        write!(out, "{}", output_format.write(&election))?;
+        return Ok(());
+    }
+    let num_choices = election.candidates.len();
+    let num_winners = args
+        .num_winners
+        .unwrap_or(election.num_winners)
+        .clamp(1, num_choices.max(1));
+    let candidates = election.candidates;
+    let ballots = election.ballots;
+    if let Some(title) = &election.title {
+        writeln!(out, "{title}")?;
+    }
+    writeln!(out, "{} ballots, {} candidates", ballots.len(), num_choices)?;
+
+    let tally = match args.method {
+        TallyMethod::RankedPairs => ranked_pairs(num_choices, ballots),
+        TallyMethod::Schulze => schulze(num_choices, ballots),
+        TallyMethod::KemenyYoung => kemeny_young(num_choices, ballots)?,
+        TallyMethod::InstantRunoff => {
+            let irv = instant_runoff(num_choices, ballots.clone());
+            writeln!(out, "Rounds:")?;
+            for (i, round) in irv.rounds.iter().enumerate() {
+                writeln!(
+                    out,
+                    "  {}. {}; eliminated {}",
+                    i + 1,
+                    print_counts(&candidates, &round.counts, round.exhausted),
+                    names(&candidates, &round.eliminated),
+                )?;
+            }
+            CondorcetTally::new(pairwise_totals(num_choices, ballots), irv.ranks, vec![])
+        }
+        TallyMethod::SingleTransferableVote => {
+            let stv = single_transferable_vote(num_choices, num_winners, ballots.clone());
+            writeln!(out, "Quota: {}", stv.quota)?;
+            writeln!(out, "Rounds:")?;
+            for (i, round) in stv.rounds.iter().enumerate() {
+                writeln!(
+                    out,
+                    "  {}. {}; elected {}; eliminated {}",
+                    i + 1,
+                    print_counts(&candidates, &round.counts, round.exhausted),
+                    names(&candidates, &round.elected),
+                    names(&candidates, &round.eliminated),
+                )?;
+            }
+            writeln!(out, "Elected: {}", names(&candidates, &stv.elected))?;
+            CondorcetTally::new(pairwise_totals(num_choices, ballots), stv.ranks, vec![])
+        }
+        TallyMethod::Approval | TallyMethod::Star => {
+            return Err("Score ballots are not supported".into())
+        }
+    };
+
+    print_ranks(out, &candidates, &tally.ranks)?;
+    print_totals(out, &candidates, &tally.totals)?;
+    writeln!(out, "Explanation:")?;
+    print_analysis(out, &candidates, &tally)?;
+    Ok(())
+}
+
+fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let args = parse_args(std::env::args().skip(1))?;
+    if args.help {
+        println!("{USAGE}");
+        return Ok(());
+    }
+    let mut input = String::new();
+    match &args.path {
+        Some(path) => input = std::fs::read_to_string(path)?,
+        None => {
+            std::io::stdin().read_to_string(&mut input)?;
+        }
+    }
+    tally(&args, &input, &mut std::io::stdout().lock())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn run_tally(args: &Args, input: &str) -> String {
+        let mut out = Vec::new();
+        tally(args, input, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_parse_method() {
+        assert_eq!(parse_method("schulze"), Some(TallyMethod::Schulze));
+        assert_eq!(
+            parse_method("single_transferable_vote"),
+            Some(TallyMethod::SingleTransferableVote)
+        );
+        assert_eq!(parse_method("Schulze"), None);
+        assert_eq!(parse_method("star"), None);
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let parsed = args(&[]).unwrap();
+        assert!(!parsed.help);
+        assert_eq!(parsed.method, TallyMethod::RankedPairs);
+        assert_eq!(parsed.num_winners, None);
+        assert_eq!(parsed.path, None);
+
+        let parsed = args(&[
+            "-m",
+            "instant_runoff",
+            "--winners",
+            "2",
+            "-f",
+            "blt",
+            "ballots.txt",
+        ])
+        .unwrap();
+        assert_eq!(parsed.method, TallyMethod::InstantRunoff);
+        assert_eq!(parsed.num_winners, Some(2));
+        assert_eq!(parsed.format, Some(Format::Blt));
+        assert_eq!(parsed.path.as_deref(), Some("ballots.txt"));
+
+        assert!(args(&["--help"]).unwrap().help);
+        assert!(args(&["ballots.abif", "-h"]).unwrap().help);
+        assert_eq!(args(&["ballots.abif", "-"]).unwrap().path, None);
+
+        assert!(args(&["--method"]).is_err());
+        assert!(args(&["--method", "borda"]).is_err());
+        assert!(args(&["--winners", "two"]).is_err());
+        assert!(args(&["--format", "csv"]).is_err());
+        assert!(args(&["--verbose"]).is_err());
+    }
+
+    #[test]
+    fn test_tally_abif() {
+        let input = "3: Alice > Bob > Carol\n2: Bob > Alice > Carol\n2: Carol > Alice > Bob\n";
+        let out = run_tally(&args(&[]).unwrap(), input);
+        assert!(out.starts_with("7 ballots, 3 candidates\n"), "{out}");
+        assert!(out.contains("  1. Alice\n  2. Bob\n  3. Carol\n"), "{out}");
+        assert!(
+            out.contains("Alice beats every other candidate head-to-head."),
+            "{out}"
+        );
+    }
+
+    #[test]
+    fn test_tally_clamps_winners() {
+        let input = "Alice > Bob > Carol\nBob > Alice > Carol\nAlice > Carol > Bob\n";
+        let parsed = args(&["--method", "single_transferable_vote", "--winners", "0"]).unwrap();
+        let out = run_tally(&parsed, input);
+        assert!(out.contains("Elected: Alice\n"), "{out}");
+    }
+}
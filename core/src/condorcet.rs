@@ -0,0 +1,847 @@
+use std::collections::HashSet;
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+/// Most candidates Kemeny-Young will rank. The search is exponential in the
+/// number of candidates.
+pub const MAX_KEMENY_YOUNG_CANDIDATES: usize = 8;
+
+#[derive(Debug, thiserror::Error)]
+pub enum KemenyYoungError {
+    #[error("Kemeny-Young supports at most {MAX_KEMENY_YOUNG_CANDIDATES} candidates, got {0}")]
+    TooManyCandidates(usize),
+}
+
+/// Check if b is reachable from a in a graph.
+fn is_reachable(a: usize, b: usize, graph: &[HashSet<usize>]) -> bool {
+    let mut discovered = HashSet::new();
+    let mut stack = vec![a];
+    while let Some(node) = stack.pop() {
+        if discovered.contains(&node) {
+            continue;
+        }
+        if node == b {
+            return true;
+        }
+        discovered.insert(node);
+        stack.extend(graph[node].iter());
+    }
+    false
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VoteItem {
+    pub candidate: usize,
+    // Lower is better.
+    pub rank: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RankedPair {
+    pub winner: usize,
+    pub loser: usize,
+    /// False if the pair was skipped because it would create a cycle.
+    pub locked: bool,
+}
+
+/// Pairs of equal strength and margin, considered together by ranked pairs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PairGroup {
+    /// Number of votes favoring each winner over its loser.
+    pub strength: u64,
+    /// Strength minus the number of votes favoring the loser.
+    pub margin: u64,
+    pub pairs: Vec<RankedPair>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CondorcetTally {
+    /// totals[a][b] contains the number of votes where candidate a beat b.
+    pub totals: Vec<Vec<u64>>,
+    // Ranks[0] contains the winner(s), ranks[n] contains the winners if you
+    // remove the members of all previous ranks.
+    pub ranks: Vec<Vec<usize>>,
+    /// Pair groups in the order ranked pairs considered them, strongest first.
+    /// Empty for other methods.
+    pub pair_groups: Vec<PairGroup>,
+    #[serde(flatten)]
+    pub analysis: CondorcetAnalysis,
+}
+
+impl CondorcetTally {
+    /// Combine a ranking with the analysis of its pairwise matrix.
+    pub fn new(totals: Vec<Vec<u64>>, ranks: Vec<Vec<usize>>, pair_groups: Vec<PairGroup>) -> Self {
+        Self {
+            analysis: analyze(&totals),
+            totals,
+            ranks,
+            pair_groups,
+        }
+    }
+}
+
+/// Properties of the pairwise majority relation that hold regardless of the
+/// method used to rank candidates.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CondorcetAnalysis {
+    /// The smallest set of candidates that each beat everyone outside.
+    pub smith_set: Vec<usize>,
+    /// The union of the smallest sets of candidates that nobody outside beats.
+    pub schwartz_set: Vec<usize>,
+    /// The candidate who beats every other candidate, if any.
+    pub condorcet_winner: Option<usize>,
+    /// Whether the majority preferences contain a cycle, i.e. a Condorcet
+    /// paradox, so that no ranking can agree with all of them.
+    pub condorcet_cycle: bool,
+}
+
+/// Analyze the pairwise matrix of an election.
+pub fn analyze(totals: &[Vec<u64>]) -> CondorcetAnalysis {
+    let num_choices = totals.len();
+    let candidates = || 0..num_choices;
+    // beats[a].contains(&b) iff a majority prefers a to b.
+    let beats: Vec<HashSet<usize>> = candidates()
+        .map(|a| {
+            candidates()
+                .filter(|&b| totals[a][b] > totals[b][a])
+                .collect()
+        })
+        .collect();
+    // beats_or_ties[a].contains(&b) iff no majority prefers b to a.
+    let beats_or_ties: Vec<HashSet<usize>> = candidates()
+        .map(|a| {
+            candidates()
+                .filter(|&b| a != b && totals[a][b] >= totals[b][a])
+                .collect()
+        })
+        .collect();
+
+    let smith_set = candidates()
+        .filter(|&a| candidates().all(|b| is_reachable(a, b, &beats_or_ties)))
+        .collect();
+    let schwartz_set = candidates()
+        .filter(|&a| {
+            candidates().all(|b| !is_reachable(b, a, &beats) || is_reachable(a, b, &beats))
+        })
+        .collect();
+    let condorcet_winner = candidates().find(|&a| beats[a].len() + 1 == num_choices);
+    let condorcet_cycle =
+        candidates().any(|a| beats[a].iter().any(|&b| is_reachable(b, a, &beats)));
+
+    CondorcetAnalysis {
+        smith_set,
+        schwartz_set,
+        condorcet_winner,
+        condorcet_cycle,
+    }
+}
+
+/// A candidate's head-to-head record against every other candidate.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CopelandScore {
+    pub wins: u64,
+    pub losses: u64,
+    pub ties: u64,
+}
+
+/// Scores under simpler methods, for comparison with the official results.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuxiliaryScores {
+    /// borda[c] contains candidate c's Borda count: one point for every
+    /// candidate ranked lower on each ballot.
+    pub borda: Vec<u64>,
+    pub copeland: Vec<CopelandScore>,
+}
+
+/// Compute each candidate's Borda count from the pairwise matrix.
+/// A ballot gives a candidate one point for every candidate it ranks lower.
+pub fn borda(totals: &[Vec<u64>]) -> Vec<u64> {
+    totals.iter().map(|row| row.iter().sum()).collect()
+}
+
+/// Compute each candidate's Copeland score from the pairwise matrix.
+pub fn copeland(totals: &[Vec<u64>]) -> Vec<CopelandScore> {
+    let num_choices = totals.len();
+    (0..num_choices)
+        .map(|a| {
+            let mut score = CopelandScore {
+                wins: 0,
+                losses: 0,
+                ties: 0,
+            };
+            for b in (0..num_choices).filter(|&b| b != a) {
+                match totals[a][b].cmp(&totals[b][a]) {
+                    std::cmp::Ordering::Greater => score.wins += 1,
+                    std::cmp::Ordering::Less => score.losses += 1,
+                    std::cmp::Ordering::Equal => score.ties += 1,
+                }
+            }
+            score
+        })
+        .collect()
+}
+
+/// Filter invalid and duplicate candidates from a ballot, and sort it from
+/// most to least preferred.
+pub fn validate_ballot(num_choices: usize, mut ballot: Vec<VoteItem>) -> Vec<VoteItem> {
+    let mut seen_candidates = vec![false; num_choices];
+    ballot.retain(|item| {
+        item.candidate < num_choices
+            && !std::mem::replace(&mut seen_candidates[item.candidate], true)
+    });
+    ballot.sort_by_key(|item| item.rank);
+    ballot
+}
+
+/// Filter invalid and duplicate candidates from ballots, and sort each
+/// ballot from most to least preferred.
+pub fn validate_ballots(num_choices: usize, votes: Vec<Vec<VoteItem>>) -> Vec<Vec<VoteItem>> {
+    votes
+        .into_iter()
+        .map(|ballot| validate_ballot(num_choices, ballot))
+        .collect()
+}
+
+/// Call f(a, b) for every pair of candidates where the ballot ranks a over b.
+fn for_each_preference(num_choices: usize, ballot: &[VoteItem], mut f: impl FnMut(usize, usize)) {
+    let ballot = validate_ballot(num_choices, ballot.to_vec());
+    for (i, item) in ballot.iter().enumerate() {
+        for item2 in ballot[i + 1..]
+            .iter()
+            .skip_while(|item2| item2.rank == item.rank)
+        {
+            f(item.candidate, item2.candidate);
+        }
+    }
+}
+
+/// Validate a ballot and add its preferences to a pairwise matrix.
+pub fn add_ballot(totals: &mut [Vec<u64>], ballot: &[VoteItem]) {
+    for_each_preference(totals.len(), ballot, |a, b| totals[a][b] += 1);
+}
+
+/// Remove a ballot previously added with `add_ballot` from a pairwise matrix.
+pub fn remove_ballot(totals: &mut [Vec<u64>], ballot: &[VoteItem]) {
+    for_each_preference(totals.len(), ballot, |a, b| totals[a][b] -= 1);
+}
+
+/// Validate ballots and compute their pairwise matrix.
+/// totals[a][b] = the number of votes ranking candidate a over candidate b.
+pub fn pairwise_totals(num_choices: usize, votes: Vec<Vec<VoteItem>>) -> Vec<Vec<u64>> {
+    let mut totals = vec![vec![0; num_choices]; num_choices];
+    for vote in votes.iter() {
+        add_ballot(&mut totals, vote);
+    }
+    totals
+}
+
+/// Rank candidates by repeatedly removing those not beaten by anyone left.
+/// `beats` must be acyclic for this to terminate.
+fn rank_undefeated(num_choices: usize, beats: impl Fn(usize, usize) -> bool) -> Vec<Vec<usize>> {
+    let mut unranked = (0..num_choices).collect::<Vec<_>>();
+    let mut ranks = vec![];
+    while !unranked.is_empty() {
+        // Find winners, i.e. undefeated nodes.
+        let winners: Vec<usize> = unranked
+            .iter()
+            .cloned()
+            .filter(|&c| !unranked.iter().any(|&c2| beats(c2, c)))
+            .collect();
+        unranked.retain(|c| !winners.contains(c));
+        ranks.push(winners);
+    }
+    ranks
+}
+
+/// Compute the results of an election using the ranked pairs method.
+pub fn ranked_pairs(num_choices: usize, votes: Vec<Vec<VoteItem>>) -> CondorcetTally {
+    ranked_pairs_from_totals(pairwise_totals(num_choices, votes))
+}
+
+/// Rank candidates by ranked pairs, given the pairwise matrix of the ballots.
+pub fn ranked_pairs_from_totals(totals: Vec<Vec<u64>>) -> CondorcetTally {
+    // See http://ericgorr.net/condorcet/rankedpairs/
+
+    let num_choices = totals.len();
+
+    // Compute the ranked pairs, a sequence of (winner, loser), sorted by:
+    // 1. strength of victory (number of votes favoring a over b)
+    // 2. margin (difference in voters favoring a vs favoring b)
+    let mut defeats = (0..num_choices)
+        .flat_map(|c| {
+            let totals = &totals;
+            (0..num_choices)
+                .filter(move |&c2| totals[c][c2] > totals[c2][c])
+                .map(move |c2| (c, c2))
+        })
+        .collect::<Vec<(usize, usize)>>();
+    // Sort stability doesn't matter because we group later.
+    defeats.sort_unstable_by_key(|&(c1, c2)| (totals[c1][c2], totals[c1][c2] - totals[c2][c1]));
+    defeats.reverse();
+
+    // defeat_graph[a].contains(&b) iff a defeats b.
+    let mut defeat_graph = vec![HashSet::new(); num_choices];
+
+    // Defeats are grouped with all equivalent defeats (by strength/margin).
+    let mut pair_groups = vec![];
+    for ((strength, opposition), current_defeats) in defeats
+        .into_iter()
+        .chunk_by(|&(a, b)| (totals[a][b], totals[b][a]))
+        .into_iter()
+    {
+        // Insert new defeats into the graph.
+        let current_defeats = current_defeats.collect::<Vec<(usize, usize)>>();
+        let margin = strength - opposition;
+        for (a, b) in current_defeats.iter().cloned() {
+            defeat_graph[a].insert(b);
+            log::trace!("considering {a} defeats {b} s{strength} m{margin}");
+        }
+
+        // Remove new defeats that are part of a cycle.
+        let defeats_in_cycles: Vec<(usize, usize)> = current_defeats
+            .iter()
+            .cloned()
+            .filter(|&(a, b)| is_reachable(b, a, &defeat_graph))
+            .collect();
+        for &(a, b) in defeats_in_cycles.iter() {
+            defeat_graph[a].remove(&b);
+        }
+        let pairs = current_defeats
+            .into_iter()
+            .map(|(winner, loser)| {
+                let locked = !defeats_in_cycles.contains(&(winner, loser));
+                if locked {
+                    log::trace!("keeping {winner} defeats {loser}");
+                }
+                RankedPair {
+                    winner,
+                    loser,
+                    locked,
+                }
+            })
+            .collect();
+        pair_groups.push(PairGroup {
+            strength,
+            margin,
+            pairs,
+        });
+    }
+
+    let ranks = rank_undefeated(num_choices, |a, b| defeat_graph[a].contains(&b));
+
+    CondorcetTally::new(totals, ranks, pair_groups)
+}
+
+/// Compute the results of an election using the Schulze (beatpath) method.
+pub fn schulze(num_choices: usize, votes: Vec<Vec<VoteItem>>) -> CondorcetTally {
+    schulze_from_totals(pairwise_totals(num_choices, votes))
+}
+
+/// Rank candidates by the Schulze method, given the pairwise matrix of the
+/// ballots.
+pub fn schulze_from_totals(totals: Vec<Vec<u64>>) -> CondorcetTally {
+    // See https://en.wikipedia.org/wiki/Schulze_method
+
+    let num_choices = totals.len();
+
+    // paths[a][b] = the strength of the strongest path from a to b, where a
+    // path is as strong as its weakest link. Like ranked pairs, links are
+    // measured in winning votes.
+    let mut paths = vec![vec![0; num_choices]; num_choices];
+    for a in 0..num_choices {
+        for b in 0..num_choices {
+            if totals[a][b] > totals[b][a] {
+                paths[a][b] = totals[a][b];
+            }
+        }
+    }
+    for i in 0..num_choices {
+        for a in (0..num_choices).filter(|&a| a != i) {
+            for b in (0..num_choices).filter(|&b| b != i && b != a) {
+                paths[a][b] = paths[a][b].max(paths[a][i].min(paths[i][b]));
+            }
+        }
+    }
+
+    let ranks = rank_undefeated(num_choices, |a, b| paths[a][b] > paths[b][a]);
+
+    CondorcetTally::new(totals, ranks, vec![])
+}
+
+/// Search for the order of `remaining` that best extends `prefix`.
+/// `agreement` is the number of pairwise preferences `prefix` agrees with.
+fn kemeny_young_search(
+    totals: &[Vec<u64>],
+    prefix: &mut Vec<usize>,
+    remaining: &mut Vec<usize>,
+    agreement: u64,
+    best: &mut (u64, Vec<usize>),
+) {
+    if remaining.is_empty() {
+        if best.1.is_empty() || agreement > best.0 {
+            *best = (agreement, prefix.clone());
+        }
+        return;
+    }
+    // Every remaining candidate will be ranked below the prefix, and at best
+    // each remaining pair is ordered the way most voters prefer.
+    let upper_bound = agreement
+        + prefix
+            .iter()
+            .flat_map(|&a| remaining.iter().map(move |&b| totals[a][b]))
+            .sum::<u64>()
+        + remaining
+            .iter()
+            .tuple_combinations()
+            .map(|(&a, &b)| totals[a][b].max(totals[b][a]))
+            .sum::<u64>();
+    if !best.1.is_empty() && upper_bound <= best.0 {
+        return;
+    }
+    for i in 0..remaining.len() {
+        let next = remaining.remove(i);
+        let gained = prefix.iter().map(|&a| totals[a][next]).sum::<u64>();
+        prefix.push(next);
+        kemeny_young_search(totals, prefix, remaining, agreement + gained, best);
+        prefix.pop();
+        remaining.insert(i, next);
+    }
+}
+
+/// Compute the results of an election using the Kemeny-Young method, which
+/// finds the ranking agreeing with the most pairwise voter preferences.
+///
+/// Candidates are ranked equally only if they are adjacent in the optimal
+/// ranking and every pair of them is tied. When several rankings are optimal,
+/// the first found is used.
+pub fn kemeny_young(
+    num_choices: usize,
+    votes: Vec<Vec<VoteItem>>,
+) -> Result<CondorcetTally, KemenyYoungError> {
+    kemeny_young_from_totals(pairwise_totals(num_choices, votes))
+}
+
+/// Rank candidates by the Kemeny-Young method, given the pairwise matrix of
+/// the ballots.
+pub fn kemeny_young_from_totals(totals: Vec<Vec<u64>>) -> Result<CondorcetTally, KemenyYoungError> {
+    // See https://en.wikipedia.org/wiki/Kemeny%E2%80%93Young_method
+
+    let num_choices = totals.len();
+    if num_choices > MAX_KEMENY_YOUNG_CANDIDATES {
+        return Err(KemenyYoungError::TooManyCandidates(num_choices));
+    }
+
+    let mut best = (0, vec![]);
+    kemeny_young_search(
+        &totals,
+        &mut vec![],
+        &mut (0..num_choices).collect(),
+        0,
+        &mut best,
+    );
+    log::trace!(
+        "best ranking {:?} agrees with {} preferences",
+        best.1,
+        best.0
+    );
+
+    let mut ranks: Vec<Vec<usize>> = vec![];
+    for c in best.1 {
+        match ranks.last_mut() {
+            Some(rank) if rank.iter().all(|&c2| totals[c][c2] == totals[c2][c]) => rank.push(c),
+            _ => ranks.push(vec![c]),
+        }
+    }
+
+    Ok(CondorcetTally::new(totals, ranks, vec![]))
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_reachable() {
+        assert!(is_reachable(0, 1, &[[1].into(), [].into()]));
+        assert!(!is_reachable(1, 0, &[[1].into(), [].into()]));
+        assert!(is_reachable(
+            0,
+            3,
+            &[[1].into(), [2].into(), [3].into(), [].into()]
+        ));
+        assert!(!is_reachable(
+            3,
+            0,
+            &[[1].into(), [2].into(), [3].into(), [].into()]
+        ));
+        let complicated_graph = &[
+            [4].into(),
+            [6].into(),
+            [6].into(),
+            [4].into(),
+            [5].into(),
+            [1, 2].into(),
+            [0].into(),
+        ];
+        assert!(is_reachable(0, 1, complicated_graph));
+        assert!(is_reachable(0, 2, complicated_graph));
+        assert!(!is_reachable(0, 3, complicated_graph));
+        assert!(is_reachable(0, 4, complicated_graph));
+        assert!(is_reachable(0, 5, complicated_graph));
+        assert!(is_reachable(0, 6, complicated_graph));
+        assert!(is_reachable(2, 0, complicated_graph));
+        assert!(is_reachable(2, 1, complicated_graph));
+        assert!(!is_reachable(2, 3, complicated_graph));
+        assert!(is_reachable(2, 4, complicated_graph));
+        assert!(is_reachable(2, 5, complicated_graph));
+        assert!(is_reachable(2, 6, complicated_graph));
+        assert!(is_reachable(3, 0, complicated_graph));
+        assert!(is_reachable(3, 1, complicated_graph));
+        assert!(is_reachable(3, 2, complicated_graph));
+        assert!(is_reachable(3, 4, complicated_graph));
+        assert!(is_reachable(3, 5, complicated_graph));
+        assert!(is_reachable(3, 6, complicated_graph));
+    }
+
+    // tt-munching macro to define example ballots.
+    // syntax looks like:
+    //     ballot!(0 > 1 = 2)
+    macro_rules! ballot {
+        // Parse usize representing a candidate, adding them to the ballot.
+        (@inner $bal:ident $rank:ident $candidate:literal $($tt:tt)*) => {
+            $bal.push(VoteItem {
+                candidate: $candidate,
+                rank: $rank,
+            });
+            ballot!(@inner $bal $rank $($tt)*)
+        };
+        // Parse `>`. Increase the rank of following candidates.
+        (@inner $bal:ident $rank:ident > $($tt:tt)*) => {
+            $rank += 1;
+            ballot!(@inner $bal $rank $($tt)*)
+        };
+        // Parse `=`. No-op.
+        (@inner $bal:ident $rank:ident = $($tt:tt)*) => {
+            ballot!(@inner $bal $rank $($tt)*)
+        };
+        // Base case: out of tokens.
+        (@inner $bal:ident $rank:ident) => {};
+        // Invalid input.
+        (@inner $($tt:tt)*) => {panic!(stringify!($($tt)*))};
+        // Top-level invocation: create a ballot.
+        ($($tt:tt)*) => {{
+            #[allow(unused_mut)]
+            let mut bal = Vec::new();
+            #[allow(unused_mut, unused_variables)]
+            let mut rank = 1;
+            ballot!(@inner bal rank $($tt)*);
+            bal
+        }}
+    }
+
+    // Macro to define a list of ballots.
+    macro_rules! ballots {
+        ($(($num:literal : $($tt:tt)*))*) => {{
+            #[allow(unused_mut)]
+            let mut ballots: Vec<Vec<VoteItem>> = Vec::new();
+            $(
+                for _ in 0..$num {
+                    ballots.push(ballot!($($tt)*));
+                }
+            )*
+            ballots
+        }}
+    }
+
+    pub(crate) use {ballot, ballots};
+
+    #[test]
+    fn test_ranked_pairs() {
+        assert_eq!(
+            ranked_pairs(
+                5,
+                ballots!(
+                    (1: 4 > 1 > 3 > 2 > 0)
+                    (1: 1 > 0 > 4 > 3 > 2)
+                    (1: 3 > 0 > 4 > 1 > 2)
+                    (1: 3 > 4 > 0 > 1 > 2)
+                    (1: 2 > 1 > 3 > 0 > 4)
+                )
+            )
+            .ranks[0],
+            vec![1, 3, 4],
+        );
+        assert_eq!(
+            ranked_pairs(2, ballots!((1: 0 > 1) (1: 1 > 0))).ranks[0],
+            vec![0, 1]
+        );
+
+        let ericgorr_example_1 = ballots!(
+            (7:0>1>2)
+            (5:1>0>2)
+            (4:2>0>1)
+            (2:1>2>0)
+        );
+        assert_eq!(ranked_pairs(3, ericgorr_example_1).ranks[0], [0]);
+
+        let ericgorr_example_2 = ballots!(
+            (40:0>1>2)
+            (35:1>2>0)
+            (25:2>0>1)
+        );
+        let tally = ranked_pairs(3, ericgorr_example_2);
+        assert_eq!(tally.ranks[0], [0]);
+        let summary: Vec<_> = tally
+            .pair_groups
+            .iter()
+            .flat_map(|group| {
+                group.pairs.iter().map(|pair| {
+                    (
+                        pair.winner,
+                        pair.loser,
+                        group.strength,
+                        group.margin,
+                        pair.locked,
+                    )
+                })
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (1, 2, 75, 50, true),
+                (0, 1, 65, 30, true),
+                (2, 0, 60, 20, false)
+            ]
+        );
+
+        let ericgorr_example_3 = ballots!(
+            (7:0>1>2)
+            (7:1>0>2)
+            (2:2>0>1)
+            (2:2>1>0)
+        );
+        assert_eq!(ranked_pairs(3, ericgorr_example_3).ranks[0], [0, 1]);
+
+        let ericgorr_example_4 = ballots!(
+            (12:0>3>2>1)
+            (3:1>0>2>3)
+            (25:1>2>0>3)
+            (21:2>1>0>3)
+            (12:3>0>1>2)
+            (21:3>0>2>1)
+            (6:3>1>0>2)
+        );
+        assert_eq!(ranked_pairs(4, ericgorr_example_4).ranks[0], [1]);
+
+        let ericgorr_interesting_1 = ballots!(
+            (12:0>3>2>1)
+            (3:1>0>2>3)
+            (25:1>2>0>3)
+            (21:2>1>0>3)
+            (12:3>0>1>2)
+            (21:3>0>2>1)
+            (6:3>1>0>2)
+        );
+        assert_eq!(ranked_pairs(4, ericgorr_interesting_1).ranks[0], [1]);
+
+        let ericgorr_interesting_2 = ballots!(
+            (280:0>2>3>1)
+            (301:1>0>2>3)
+            (303:2>1>3>0)
+            (356:3>0>1>2)
+        );
+        assert_eq!(ranked_pairs(4, ericgorr_interesting_2).ranks[0], [0]);
+    }
+
+    #[test]
+    fn test_schulze() {
+        // https://en.wikipedia.org/wiki/Schulze_method#Example
+        let wikipedia_example = ballots!(
+            (5:0>2>1>4>3)
+            (5:0>3>4>2>1)
+            (8:1>4>3>0>2)
+            (3:2>0>1>4>3)
+            (7:2>0>4>1>3)
+            (2:2>1>0>3>4)
+            (7:3>2>4>1>0)
+            (8:4>1>0>3>2)
+        );
+        assert_eq!(
+            schulze(5, wikipedia_example).ranks,
+            vec![vec![4], vec![0], vec![2], vec![1], vec![3]]
+        );
+
+        assert_eq!(
+            schulze(2, ballots!((1: 0 > 1) (1: 1 > 0))).ranks[0],
+            vec![0, 1]
+        );
+
+        let ericgorr_example_2 = ballots!(
+            (40:0>1>2)
+            (35:1>2>0)
+            (25:2>0>1)
+        );
+        assert_eq!(schulze(3, ericgorr_example_2).ranks[0], [0]);
+
+        let ericgorr_example_3 = ballots!(
+            (7:0>1>2)
+            (7:1>0>2)
+            (2:2>0>1)
+            (2:2>1>0)
+        );
+        assert_eq!(schulze(3, ericgorr_example_3).ranks[0], [0, 1]);
+    }
+
+    #[test]
+    fn test_analyze() {
+        let ericgorr_example_1 = ballots!(
+            (7:0>1>2)
+            (5:1>0>2)
+            (4:2>0>1)
+            (2:1>2>0)
+        );
+        let analysis = analyze(&ranked_pairs(3, ericgorr_example_1).totals);
+        assert_eq!(analysis.smith_set, vec![0]);
+        assert_eq!(analysis.schwartz_set, vec![0]);
+        assert_eq!(analysis.condorcet_winner, Some(0));
+        assert!(!analysis.condorcet_cycle);
+
+        let ericgorr_example_2 = ballots!(
+            (40:0>1>2)
+            (35:1>2>0)
+            (25:2>0>1)
+        );
+        let analysis = analyze(&ranked_pairs(3, ericgorr_example_2).totals);
+        assert_eq!(analysis.smith_set, vec![0, 1, 2]);
+        assert_eq!(analysis.schwartz_set, vec![0, 1, 2]);
+        assert_eq!(analysis.condorcet_winner, None);
+        assert!(analysis.condorcet_cycle);
+
+        // 0 ties 1, 1 beats 2, 2 beats 0.
+        let analysis = analyze(&ranked_pairs(3, ballots!((1: 1 > 2 > 0) (1: 0 > 1))).totals);
+        assert_eq!(analysis.smith_set, vec![0, 1, 2]);
+        assert_eq!(analysis.schwartz_set, vec![1]);
+        assert_eq!(analysis.condorcet_winner, None);
+        assert!(!analysis.condorcet_cycle);
+
+        let analysis = analyze(&ranked_pairs(2, ballots!((1: 0 > 1) (1: 1 > 0))).totals);
+        assert_eq!(analysis.smith_set, vec![0, 1]);
+        assert_eq!(analysis.schwartz_set, vec![0, 1]);
+        assert_eq!(analysis.condorcet_winner, None);
+        assert!(!analysis.condorcet_cycle);
+    }
+
+    #[test]
+    fn test_add_and_remove_ballot() {
+        let votes = ballots!(
+            (3: 0 > 1 > 2)
+            (2: 2 = 1 > 0)
+            (1: 1)
+            (1: 2 > 5 > 0 > 2)
+        );
+        let mut totals = vec![vec![0; 3]; 3];
+        for vote in votes.iter() {
+            add_ballot(&mut totals, vote);
+        }
+        assert_eq!(totals, pairwise_totals(3, votes.clone()));
+        assert_eq!(totals, vec![vec![0, 3, 3], vec![2, 0, 3], vec![3, 0, 0]]);
+
+        // Replacing a ballot matches recomputing from scratch.
+        remove_ballot(&mut totals, &votes[0]);
+        add_ballot(&mut totals, &ballot!(2 > 0));
+        let mut replaced = votes.clone();
+        replaced[0] = ballot!(2 > 0);
+        assert_eq!(totals, pairwise_totals(3, replaced));
+        for vote in votes[1..].iter() {
+            remove_ballot(&mut totals, vote);
+        }
+        assert_eq!(totals, vec![vec![0, 0, 0], vec![0, 0, 0], vec![1, 0, 0]]);
+        assert_eq!(
+            ranked_pairs_from_totals(totals.clone()).ranks,
+            ranked_pairs(3, ballots!((1: 2 > 0))).ranks
+        );
+    }
+
+    #[test]
+    fn test_borda_and_copeland() {
+        let ericgorr_example_1 = ballots!(
+            (7:0>1>2)
+            (5:1>0>2)
+            (4:2>0>1)
+            (2:1>2>0)
+        );
+        let totals = ranked_pairs(3, ericgorr_example_1).totals;
+        assert_eq!(borda(&totals), vec![23, 21, 10]);
+        assert_eq!(
+            copeland(&totals),
+            vec![
+                CopelandScore {
+                    wins: 2,
+                    losses: 0,
+                    ties: 0
+                },
+                CopelandScore {
+                    wins: 1,
+                    losses: 1,
+                    ties: 0
+                },
+                CopelandScore {
+                    wins: 0,
+                    losses: 2,
+                    ties: 0
+                },
+            ]
+        );
+
+        // Equal ranks and unranked candidates earn no points against each other.
+        let totals = ranked_pairs(3, ballots!((1: 0 = 1 > 2) (1: 2))).totals;
+        assert_eq!(borda(&totals), vec![1, 1, 0]);
+        assert_eq!(
+            copeland(&totals)[0],
+            CopelandScore {
+                wins: 1,
+                losses: 0,
+                ties: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_kemeny_young() {
+        // https://en.wikipedia.org/wiki/Kemeny%E2%80%93Young_method#Example
+        let tennessee = ballots!(
+            (42:0>1>2>3)
+            (26:1>2>3>0)
+            (15:2>3>1>0)
+            (17:3>2>1>0)
+        );
+        assert_eq!(
+            kemeny_young(4, tennessee).unwrap().ranks,
+            vec![vec![1], vec![2], vec![3], vec![0]]
+        );
+
+        let ericgorr_example_2 = ballots!(
+            (40:0>1>2)
+            (35:1>2>0)
+            (25:2>0>1)
+        );
+        assert_eq!(
+            kemeny_young(3, ericgorr_example_2).unwrap().ranks,
+            vec![vec![0], vec![1], vec![2]]
+        );
+
+        assert_eq!(
+            kemeny_young(3, ballots!((1: 0 > 1) (1: 1 > 0) (1: 2)))
+                .unwrap()
+                .ranks,
+            vec![vec![0, 1, 2]]
+        );
+
+        assert!(matches!(
+            kemeny_young(9, vec![]),
+            Err(KemenyYoungError::TooManyCandidates(9))
+        ));
+    }
+}
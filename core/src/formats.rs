@@ -0,0 +1,269 @@
+//! Ballot file formats used by other election tools.
+use std::collections::HashMap;
+
+use itertools::Itertools;
+
+use crate::condorcet::VoteItem;
+
+pub mod abif;
+pub mod blt;
+pub mod preflib;
+
+/// Most ballots a file may hold. Counts are expanded into separate ballots,
+/// so a large count on one line would otherwise use unbounded memory.
+pub const MAX_BALLOTS: usize = 1_000_000;
+
+/// An election read from or written to a ballot file.
+#[derive(Clone, Debug)]
+pub struct Election {
+    pub title: Option<String>,
+    pub candidates: Vec<String>,
+    /// Number of seats to fill. Only BLT files record this; others use 1.
+    pub num_winners: usize,
+    /// Ballots with ranks numbered 0, 1, 2, ... from best to worst.
+    pub ballots: Vec<Vec<VoteItem>>,
+}
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+#[error("line {line}: {message}")]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Abif,
+    Blt,
+    PrefLib,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "abif" => Some(Self::Abif),
+            "blt" => Some(Self::Blt),
+            "preflib" | "soc" | "soi" | "toc" | "toi" => Some(Self::PrefLib),
+            _ => None,
+        }
+    }
+
+    /// Guess the format of a file from its extension.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let (_, extension) = path.rsplit_once('.')?;
+        Self::from_name(&extension.to_lowercase())
+    }
+
+    pub fn parse(self, input: &str) -> Result<Election, ParseError> {
+        match self {
+            Self::Abif => abif::parse(input),
+            Self::Blt => blt::parse(input),
+            Self::PrefLib => preflib::parse(input),
+        }
+    }
+
+    pub fn write(self, election: &Election) -> String {
+        match self {
+            Self::Abif => abif::write(election),
+            Self::Blt => blt::write(election),
+            Self::PrefLib => preflib::write(election),
+        }
+    }
+}
+
+/// Escape backslashes and `close` with a backslash, so that the name can be
+/// written between delimiters and read back by [`unescape`].
+fn escape(name: &str, close: char) -> String {
+    name.replace('\\', "\\\\")
+        .replace(close, &format!("\\{close}"))
+}
+
+/// Read a name written by [`escape`] up to the first unescaped `close`.
+/// Returns the name and the rest of the line, or `None` if `close` is missing.
+fn unescape(line: &str, close: char) -> Option<(String, &str)> {
+    let mut name = String::new();
+    let mut chars = line.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => name.push(chars.next()?.1),
+            _ if c == close => return Some((name, &line[i + c.len_utf8()..])),
+            _ => name.push(c),
+        }
+    }
+    None
+}
+
+/// Groups of equally ranked candidates, best first.
+fn rank_groups(ballot: &[VoteItem]) -> Vec<Vec<usize>> {
+    ballot
+        .iter()
+        .sorted_by_key(|item| item.rank)
+        .chunk_by(|item| item.rank)
+        .into_iter()
+        .map(|(_, group)| group.map(|item| item.candidate).collect())
+        .collect()
+}
+
+/// Identical ballots and their counts, in order of first appearance.
+fn count_ballots(ballots: &[Vec<VoteItem>]) -> Vec<(usize, Vec<Vec<usize>>)> {
+    let mut counts: Vec<(usize, Vec<Vec<usize>>)> = vec![];
+    let mut index: HashMap<Vec<Vec<usize>>, usize> = HashMap::new();
+    for ballot in ballots {
+        let groups = rank_groups(ballot);
+        match index.get(&groups) {
+            Some(&i) => counts[i].0 += 1,
+            None => {
+                index.insert(groups.clone(), counts.len());
+                counts.push((1, groups));
+            }
+        }
+    }
+    counts
+}
+
+/// Collects a parsed ballot, checking that each candidate appears only once.
+struct BallotBuilder {
+    line: usize,
+    num_choices: usize,
+    ballot: Vec<VoteItem>,
+    rank: u64,
+}
+
+impl BallotBuilder {
+    fn new(line: usize, num_choices: usize) -> Self {
+        Self {
+            line,
+            num_choices,
+            ballot: vec![],
+            rank: 0,
+        }
+    }
+
+    /// Add a group of equally ranked candidates below all previous groups.
+    fn push_group(&mut self, group: &[usize]) -> Result<(), ParseError> {
+        for &candidate in group {
+            if candidate >= self.num_choices {
+                return Err(ParseError::new(
+                    self.line,
+                    format!("unknown candidate {}", candidate + 1),
+                ));
+            }
+            if self.ballot.iter().any(|item| item.candidate == candidate) {
+                return Err(ParseError::new(
+                    self.line,
+                    format!("candidate {} ranked twice", candidate + 1),
+                ));
+            }
+            self.ballot.push(VoteItem {
+                candidate,
+                rank: self.rank,
+            });
+        }
+        self.rank += 1;
+        Ok(())
+    }
+
+    fn has_ties(&self) -> bool {
+        self.ballot.len() as u64 != self.rank
+    }
+
+    /// Push count copies of the ballot.
+    fn finish(self, count: usize, ballots: &mut Vec<Vec<VoteItem>>) -> Result<(), ParseError> {
+        if count > MAX_BALLOTS - ballots.len() {
+            return Err(ParseError::new(
+                self.line,
+                format!("more than {MAX_BALLOTS} ballots"),
+            ));
+        }
+        ballots.extend(std::iter::repeat_n(self.ballot, count));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::condorcet::test::{ballot, ballots};
+
+    pub(crate) fn election() -> Election {
+        Election {
+            title: Some("Board election".into()),
+            candidates: vec!["Alice".into(), "Bob Jones".into(), "Carol".into()],
+            num_winners: 1,
+            ballots: ballots!(
+                (2: 0 > 1 > 2)
+                (1: 1 = 2 > 0)
+                (1: 2)
+                (1: )
+                (3: 2 > 0 = 1)
+            ),
+        }
+    }
+
+    /// Check that two elections are the same up to the order of ballots and
+    /// the numbering of ranks.
+    pub(crate) fn assert_same_election(a: &Election, b: &Election) {
+        assert_eq!(a.title, b.title);
+        assert_eq!(a.candidates, b.candidates);
+        assert_eq!(a.num_winners, b.num_winners);
+        assert_eq!(count_ballots(&a.ballots), count_ballots(&b.ballots));
+    }
+
+    #[test]
+    fn test_count_ballots() {
+        assert_eq!(
+            count_ballots(&election().ballots),
+            vec![
+                (2, vec![vec![0], vec![1], vec![2]]),
+                (1, vec![vec![1, 2], vec![0]]),
+                (1, vec![vec![2]]),
+                (1, vec![]),
+                (3, vec![vec![2], vec![0, 1]]),
+            ]
+        );
+        let gapped = vec![
+            VoteItem {
+                candidate: 1,
+                rank: 9,
+            },
+            VoteItem {
+                candidate: 0,
+                rank: 4,
+            },
+        ];
+        assert_eq!(
+            count_ballots(&[gapped]),
+            count_ballots(&ballots!((1: 0 > 1)))
+        );
+        assert_eq!(rank_groups(&ballot!(1 > 2 = 0)), vec![vec![1], vec![2, 0]]);
+    }
+
+    #[test]
+    fn test_escape() {
+        let name = r"a\b]c";
+        assert_eq!(escape(name, ']'), r"a\\b\]c");
+        assert_eq!(
+            unescape(&format!("{}] > B", escape(name, ']')), ']'),
+            Some((name.to_owned(), " > B"))
+        );
+        assert_eq!(unescape(r"a\]", ']'), None);
+        assert_eq!(unescape("a", ']'), None);
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(Format::from_path("a/b.BLT"), Some(Format::Blt));
+        assert_eq!(Format::from_path("votes.toc"), Some(Format::PrefLib));
+        assert_eq!(Format::from_path("votes.abif"), Some(Format::Abif));
+        assert_eq!(Format::from_path("votes"), None);
+    }
+}
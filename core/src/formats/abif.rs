@@ -0,0 +1,353 @@
+//! The Aggregated Ballot Information Format, see https://electowiki.org/wiki/ABIF.
+//!
+//! ```text
+//! # Comments start with #.
+//! {title: "Board election"}
+//! =A : [Alice Smith]
+//! 12: A > [Bob Jones] = Carol
+//! 3: Carol
+//! ```
+//!
+//! Candidates are numbered in order of declaration, then first appearance.
+//! A ballot's count may be left out, in which case it is 1. Rated ballots are
+//! not supported. A backslash escapes the next character in bracketed names
+//! and the quoted title, so that they can hold `]` and `"`.
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+use super::{count_ballots, escape, unescape, BallotBuilder, Election, ParseError};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token<'a> {
+    Candidate(Cow<'a, str>),
+    Prefer,
+    Equal,
+}
+
+fn is_bare(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+/// Split a line into tokens, stopping at comments.
+fn tokenize(line_number: usize, mut line: &str) -> Result<Vec<Token<'_>>, ParseError> {
+    let mut tokens = vec![];
+    loop {
+        line = line.trim_start();
+        let Some(c) = line.chars().next() else {
+            return Ok(tokens);
+        };
+        match c {
+            '#' => return Ok(tokens),
+            '>' => tokens.push(Token::Prefer),
+            '=' => tokens.push(Token::Equal),
+            '[' => {
+                let (name, rest) = unescape(&line[1..], ']')
+                    .ok_or_else(|| ParseError::new(line_number, "unclosed ["))?;
+                tokens.push(Token::Candidate(Cow::Owned(name)));
+                line = rest;
+                continue;
+            }
+            _ if is_bare(c) => {
+                let end = line.find(|c| !is_bare(c)).unwrap_or(line.len());
+                tokens.push(Token::Candidate(Cow::Borrowed(&line[..end])));
+                line = &line[end..];
+                continue;
+            }
+            '/' | ',' => {
+                return Err(ParseError::new(
+                    line_number,
+                    "rated ballots are not supported",
+                ))
+            }
+            _ => return Err(ParseError::new(line_number, format!("unexpected {c:?}"))),
+        }
+        line = &line[1..];
+    }
+}
+
+/// Returns the count of a ballot line, if given, and the rest of the line.
+fn split_count(line_number: usize, line: &str) -> Result<(Option<usize>, &str), ParseError> {
+    let digits = line
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(line.len());
+    let rest = line[digits..].trim_start();
+    match rest.strip_prefix([':', '*']) {
+        Some(rest) if digits > 0 => {
+            let count = line[..digits]
+                .parse()
+                .map_err(|e| ParseError::new(line_number, format!("invalid count: {e}")))?;
+            Ok((Some(count), rest))
+        }
+        _ => Ok((None, line)),
+    }
+}
+
+/// Splits a declaration like `A : [Alice Smith]` into its token and name.
+/// The token may be bracketed and hold a colon itself.
+fn split_declaration(declaration: &str) -> (&str, &str) {
+    let declaration = declaration.trim_start();
+    let token_len = match declaration.strip_prefix('[') {
+        Some(rest) => unescape(rest, ']').map_or(0, |(_, after)| declaration.len() - after.len()),
+        None => 0,
+    };
+    match declaration[token_len..].split_once(':') {
+        Some((token, name)) => (&declaration[..token_len + token.len()], name),
+        None => (declaration, ""),
+    }
+}
+
+/// Returns the value of the title in a metadata line like `{title: "..."}`.
+fn parse_title(line: &str) -> Option<String> {
+    let line = line.trim().strip_prefix('{')?.strip_suffix('}')?;
+    let (key, value) = line.split_once(':')?;
+    if key.trim().trim_matches('"') != "title" {
+        return None;
+    }
+    let value = value.trim();
+    match value.strip_prefix('"') {
+        Some(quoted) => match unescape(quoted, '"')? {
+            (title, "") => Some(title),
+            _ => None,
+        },
+        None => Some(value.to_owned()),
+    }
+}
+
+#[derive(Default)]
+struct Candidates {
+    names: Vec<String>,
+    /// Candidate index of each declared token and name.
+    ids: HashMap<String, usize>,
+}
+
+impl Candidates {
+    fn get_or_insert(&mut self, token: &str) -> usize {
+        match self.ids.get(token) {
+            Some(&candidate) => candidate,
+            None => {
+                self.names.push(token.to_owned());
+                self.ids.insert(token.to_owned(), self.names.len() - 1);
+                self.names.len() - 1
+            }
+        }
+    }
+}
+
+pub fn parse(input: &str) -> Result<Election, ParseError> {
+    let mut title = None;
+    let mut candidates = Candidates::default();
+    let mut parsed = vec![];
+    for (i, line) in input.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.trim();
+        if line.starts_with('{') {
+            title = parse_title(line).or(title);
+            continue;
+        }
+        if let Some(declaration) = line.strip_prefix('=') {
+            let (token, name) = split_declaration(declaration);
+            let candidate = match &tokenize(line_number, token)?[..] {
+                [Token::Candidate(token)] => candidates.get_or_insert(token),
+                _ => return Err(ParseError::new(line_number, "expected candidate token")),
+            };
+            match &tokenize(line_number, name)?[..] {
+                [] => {}
+                [Token::Candidate(name)] => {
+                    candidates.names[candidate] = name.to_string();
+                    candidates.ids.insert(name.to_string(), candidate);
+                }
+                _ => return Err(ParseError::new(line_number, "expected candidate name")),
+            }
+            continue;
+        }
+        let (count, preferences) = split_count(line_number, line)?;
+        let tokens = tokenize(line_number, preferences)?;
+        if tokens.is_empty() && count.is_none() {
+            continue;
+        }
+        // Resolve candidates after reading every declaration.
+        let mut groups: Vec<Vec<Cow<str>>> = vec![];
+        let mut separator = Some(Token::Prefer);
+        for token in tokens {
+            match (token, separator.take()) {
+                (Token::Candidate(name), Some(Token::Prefer)) => groups.push(vec![name]),
+                (Token::Candidate(name), Some(_)) => groups.last_mut().unwrap().push(name),
+                (Token::Candidate(name), None) => {
+                    return Err(ParseError::new(
+                        line_number,
+                        format!("expected > or = before {name:?}"),
+                    ))
+                }
+                (token, None) => separator = Some(token),
+                (_, Some(_)) => return Err(ParseError::new(line_number, "expected candidate")),
+            }
+        }
+        if separator.is_some() && !groups.is_empty() {
+            return Err(ParseError::new(line_number, "expected candidate"));
+        }
+        parsed.push((line_number, count.unwrap_or(1), groups));
+    }
+
+    let mut ballots = vec![];
+    for (line_number, count, groups) in parsed {
+        let mut seen = HashSet::new();
+        let mut ids = vec![];
+        for group in groups {
+            let mut group_ids = vec![];
+            for name in group {
+                let candidate = candidates.get_or_insert(&name);
+                if !seen.insert(candidate) {
+                    return Err(ParseError::new(
+                        line_number,
+                        format!("{name:?} ranked twice"),
+                    ));
+                }
+                group_ids.push(candidate);
+            }
+            ids.push(group_ids);
+        }
+        let mut ballot = BallotBuilder::new(line_number, candidates.names.len());
+        for group in ids {
+            ballot.push_group(&group)?;
+        }
+        ballot.finish(count, &mut ballots)?;
+    }
+    Ok(Election {
+        title,
+        candidates: candidates.names,
+        num_winners: 1,
+        ballots,
+    })
+}
+
+fn write_name(name: &str) -> String {
+    if !name.is_empty() && name.chars().all(is_bare) {
+        name.to_owned()
+    } else {
+        format!("[{}]", escape(name, ']'))
+    }
+}
+
+pub fn write(election: &Election) -> String {
+    let mut output = String::new();
+    if let Some(title) = &election.title {
+        output += &format!("{{title: \"{}\"}}\n", escape(title, '"'));
+    }
+    // Declare every candidate so that unranked ones keep their place.
+    for name in election.candidates.iter() {
+        output += &format!("={}\n", write_name(name));
+    }
+    for (count, groups) in count_ballots(&election.ballots) {
+        let preferences = groups
+            .iter()
+            .map(|group| {
+                group
+                    .iter()
+                    .map(|&candidate| write_name(&election.candidates[candidate]))
+                    .collect::<Vec<_>>()
+                    .join(" = ")
+            })
+            .collect::<Vec<_>>()
+            .join(" > ");
+        output += format!("{count}: {preferences}").trim_end();
+        output.push('\n');
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::condorcet::{
+        test::{ballot, ballots},
+        VoteItem,
+    };
+    use crate::formats::test::{assert_same_election, election};
+
+    fn error(input: &str) -> String {
+        parse(input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let election = election();
+        let written = write(&election);
+        assert_eq!(
+            written,
+            "{title: \"Board election\"}\n=Alice\n=[Bob Jones]\n=Carol\n\
+             2: Alice > [Bob Jones] > Carol\n1: [Bob Jones] = Carol > Alice\n\
+             1: Carol\n1:\n3: Carol > Alice = [Bob Jones]\n"
+        );
+        assert_same_election(&parse(&written).unwrap(), &election);
+    }
+
+    #[test]
+    fn test_round_trip_escapes() {
+        let election = Election {
+            title: Some(r#"The "best" \ election"#.into()),
+            candidates: vec!["[A]".into(), "Option 2: B".into(), r"C\".into()],
+            num_winners: 1,
+            ballots: ballots!((1: 0 > 1 = 2) (2: 2)),
+        };
+        let written = write(&election);
+        assert_eq!(
+            written,
+            "{title: \"The \\\"best\\\" \\\\ election\"}\n\
+             =[[A\\]]\n=[Option 2: B]\n=[C\\\\]\n\
+             1: [[A\\]] > [Option 2: B] = [C\\\\]\n2: [C\\\\]\n"
+        );
+        assert_same_election(&parse(&written).unwrap(), &election);
+    }
+
+    #[test]
+    fn test_parse() {
+        let parsed = parse(
+            "# An election\n\
+             {version: \"0.1\"}\n\
+             =A : [Alice Smith] # declared\n\
+             \n\
+             12: A > [Bob Jones]=Carol\n\
+             3 * Carol>[Alice Smith]\n\
+             Bob_Jones\n",
+        )
+        .unwrap();
+        assert_eq!(parsed.title, None);
+        assert_same_election(
+            &parsed,
+            &Election {
+                title: None,
+                candidates: vec![
+                    "Alice Smith".into(),
+                    "Bob Jones".into(),
+                    "Carol".into(),
+                    "Bob_Jones".into(),
+                ],
+                num_winners: 1,
+                ballots: ballots!((12: 0 > 1 = 2) (3: 2 > 0) (1: 3)),
+            },
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(error("\n1: A > \n"), "line 2: expected candidate");
+        assert_eq!(error("1: A > > B\n"), "line 1: expected candidate");
+        assert_eq!(error("1: = A\n"), "line 1: expected candidate");
+        assert_eq!(error("1: A B\n"), "line 1: expected > or = before \"B\"");
+        assert_eq!(error("1: A > [B\n"), "line 1: unclosed [");
+        assert_eq!(
+            error("1: A/5, B/3\n"),
+            "line 1: rated ballots are not supported"
+        );
+        assert_eq!(error("1: A > B > A\n"), "line 1: \"A\" ranked twice");
+        assert_eq!(
+            error("=A : [Alice]\n1: A = [Alice]\n"),
+            "line 2: \"Alice\" ranked twice"
+        );
+        assert_eq!(error("1: A ; B\n"), "line 1: unexpected ';'");
+        assert_eq!(
+            error("1: A\n1000000: B\n"),
+            "line 2: more than 1000000 ballots"
+        );
+    }
+}
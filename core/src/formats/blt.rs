@@ -0,0 +1,270 @@
+//! The `.blt` format used by OpenSTV and other STV counting programs.
+//!
+//! ```text
+//! 3 1          number of candidates and seats
+//! -2           optional: candidates who withdrew
+//! 4 1 2 3 0    weight, then candidates from best to worst, then 0
+//! 1 2=3 1 0    equally ranked candidates are joined by `=`
+//! 0            end of ballots
+//! "Alice"      candidate names
+//! "Bob"
+//! "Carol"
+//! "Title"      optional
+//! ```
+//!
+//! A backslash escapes the next character in names, e.g. `"Bob \"BJ\" Jones"`.
+use super::{count_ballots, escape, unescape, BallotBuilder, Election, ParseError};
+
+/// Returns the contents of a quoted string, e.g. a candidate name.
+fn parse_quoted(line_number: usize, line: &str) -> Result<String, ParseError> {
+    line.strip_prefix('"')
+        .and_then(|line| unescape(line, '"'))
+        .and_then(|(name, rest)| rest.is_empty().then_some(name))
+        .ok_or_else(|| ParseError::new(line_number, format!("expected quoted name, got {line:?}")))
+}
+
+fn parse_number(line_number: usize, token: &str, what: &str) -> Result<usize, ParseError> {
+    token
+        .parse()
+        .map_err(|_| ParseError::new(line_number, format!("invalid {what} {token:?}")))
+}
+
+/// Parses a candidate number, counting from 1, into a candidate index.
+fn parse_candidate(line_number: usize, token: &str) -> Result<usize, ParseError> {
+    parse_number(line_number, token, "candidate")?
+        .checked_sub(1)
+        .ok_or_else(|| ParseError::new(line_number, "candidates are numbered from 1"))
+}
+
+pub fn parse(input: &str) -> Result<Election, ParseError> {
+    let mut lines = input
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty());
+    let (line_number, header) = lines
+        .next()
+        .ok_or_else(|| ParseError::new(1, "missing number of candidates and seats"))?;
+    let (num_choices, num_winners) = match header.split_whitespace().collect::<Vec<_>>()[..] {
+        [num_choices, num_winners] => (
+            parse_number(line_number, num_choices, "number of candidates")?,
+            parse_number(line_number, num_winners, "number of seats")?,
+        ),
+        _ => {
+            return Err(ParseError::new(
+                line_number,
+                "expected number of candidates and seats",
+            ))
+        }
+    };
+
+    let mut withdrawn = vec![];
+    let mut ballots = vec![];
+    let mut lines = lines.peekable();
+    if let Some((line_number, line)) = lines.next_if(|(_, line)| line.starts_with('-')) {
+        for token in line.split_whitespace() {
+            let candidate = token.strip_prefix('-').ok_or_else(|| {
+                ParseError::new(line_number, format!("invalid withdrawal {token:?}"))
+            })?;
+            withdrawn.push(parse_candidate(line_number, candidate)?);
+        }
+    }
+    loop {
+        let (line_number, line) = lines
+            .next()
+            .ok_or_else(|| ParseError::new(input.lines().count(), "missing 0 after ballots"))?;
+        if line == "0" {
+            break;
+        }
+        let mut tokens = line.split_whitespace().peekable();
+        // Some programs put a ballot ID in parentheses first.
+        tokens.next_if(|token| token.starts_with('('));
+        let weight = parse_number(line_number, tokens.next().unwrap_or(""), "ballot weight")?;
+        let mut ballot = BallotBuilder::new(line_number, num_choices);
+        let mut terminated = false;
+        for token in tokens.by_ref() {
+            if token == "0" {
+                terminated = true;
+                break;
+            }
+            let group = token
+                .split('=')
+                .map(|candidate| parse_candidate(line_number, candidate))
+                .filter(|candidate| !matches!(candidate, Ok(c) if withdrawn.contains(c)))
+                .collect::<Result<Vec<_>, _>>()?;
+            if !group.is_empty() {
+                ballot.push_group(&group)?;
+            }
+        }
+        if !terminated {
+            return Err(ParseError::new(line_number, "ballot must end with 0"));
+        }
+        if let Some(token) = tokens.next() {
+            return Err(ParseError::new(
+                line_number,
+                format!("unexpected {token:?} after end of ballot"),
+            ));
+        }
+        ballot.finish(weight, &mut ballots)?;
+    }
+
+    let mut candidates = vec![];
+    for _ in 0..num_choices {
+        let (line_number, line) = lines.next().ok_or_else(|| {
+            ParseError::new(
+                input.lines().count(),
+                format!(
+                    "expected {num_choices} candidate names, got {}",
+                    candidates.len()
+                ),
+            )
+        })?;
+        candidates.push(parse_quoted(line_number, line)?);
+    }
+    let title = lines
+        .next()
+        .map(|(line_number, line)| parse_quoted(line_number, line))
+        .transpose()?;
+    if let Some((line_number, line)) = lines.next() {
+        return Err(ParseError::new(
+            line_number,
+            format!("unexpected {line:?} after title"),
+        ));
+    }
+    Ok(Election {
+        title,
+        candidates,
+        num_winners,
+        ballots,
+    })
+}
+
+pub fn write(election: &Election) -> String {
+    let mut output = format!("{} {}\n", election.candidates.len(), election.num_winners);
+    for (count, groups) in count_ballots(&election.ballots) {
+        output += &count.to_string();
+        for group in groups {
+            output.push(' ');
+            output += &group
+                .iter()
+                .map(|candidate| (candidate + 1).to_string())
+                .collect::<Vec<_>>()
+                .join("=");
+        }
+        output += " 0\n";
+    }
+    output += "0\n";
+    for name in election.candidates.iter().chain(election.title.as_ref()) {
+        output += &format!("\"{}\"\n", escape(name, '"'));
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::condorcet::{
+        test::{ballot, ballots},
+        VoteItem,
+    };
+    use crate::formats::test::{assert_same_election, election};
+
+    fn error(input: &str) -> String {
+        parse(input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let election = election();
+        let written = write(&election);
+        assert_eq!(
+            written,
+            "3 1\n2 1 2 3 0\n1 2=3 1 0\n1 3 0\n1 0\n3 3 1=2 0\n0\n\
+             \"Alice\"\n\"Bob Jones\"\n\"Carol\"\n\"Board election\"\n"
+        );
+        assert_same_election(&parse(&written).unwrap(), &election);
+    }
+
+    #[test]
+    fn test_round_trip_escapes() {
+        let election = Election {
+            title: Some(r"C:\votes".into()),
+            candidates: vec![r#"Bob "BJ" Jones"#.into(), "\"".into()],
+            num_winners: 1,
+            ballots: ballots!((1: 0 > 1)),
+        };
+        let written = write(&election);
+        assert_eq!(
+            written,
+            r#"2 1
+1 1 2 0
+0
+"Bob \"BJ\" Jones"
+"\""
+"C:\\votes"
+"#
+        );
+        assert_same_election(&parse(&written).unwrap(), &election);
+    }
+
+    #[test]
+    fn test_parse() {
+        let parsed =
+            parse("4 2\n-3\n\n(a) 2 1 3 2 0\n1 4 3=1 0\n0\n\"A\"\n\"B\"\n\"C\"\n\"D\"\n").unwrap();
+        assert_eq!(parsed.num_winners, 2);
+        assert_eq!(parsed.title, None);
+        assert_same_election(
+            &parsed,
+            &Election {
+                title: None,
+                candidates: vec!["A".into(), "B".into(), "C".into(), "D".into()],
+                num_winners: 2,
+                ballots: ballots!((2: 0 > 1) (1: 3 > 0)),
+            },
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(error(""), "line 1: missing number of candidates and seats");
+        assert_eq!(
+            error("2\n"),
+            "line 1: expected number of candidates and seats"
+        );
+        assert_eq!(error("2 1\n1 1 2\n0\n"), "line 2: ballot must end with 0");
+        assert_eq!(error("2 1\n1 1 3 0\n0\n"), "line 2: unknown candidate 3");
+        assert_eq!(
+            error("2 1\n1 1 1 0\n0\n"),
+            "line 2: candidate 1 ranked twice"
+        );
+        assert_eq!(
+            error("2 1\n1 1=0 0\n0\n"),
+            "line 2: candidates are numbered from 1"
+        );
+        assert_eq!(
+            error("2 1\nx 1 0\n0\n"),
+            "line 2: invalid ballot weight \"x\""
+        );
+        assert_eq!(
+            error("2 1\n1 0 1\n0\n"),
+            "line 2: unexpected \"1\" after end of ballot"
+        );
+        assert_eq!(error("2 1\n1 1 0\n"), "line 2: missing 0 after ballots");
+        assert_eq!(
+            error("2 1\n1000001 1 0\n0\n"),
+            "line 2: more than 1000000 ballots"
+        );
+        assert_eq!(
+            error("2 1\n0\n\"A\"B\"\n"),
+            "line 3: expected quoted name, got \"\\\"A\\\"B\\\"\""
+        );
+        assert_eq!(
+            error("2 1\n0\n\"A\"\nB\n"),
+            "line 4: expected quoted name, got \"B\""
+        );
+        assert_eq!(
+            error("2 1\n0\n\"A\"\n"),
+            "line 3: expected 2 candidate names, got 1"
+        );
+    }
+}
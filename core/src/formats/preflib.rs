@@ -0,0 +1,317 @@
+//! PrefLib's ordinal preference formats, see https://preflib.github.io/PrefLib-Jekyll/format.
+//!
+//! ```text
+//! # DATA TYPE: toc
+//! # NUMBER ALTERNATIVES: 3
+//! # ALTERNATIVE NAME 1: Alice
+//! # ALTERNATIVE NAME 2: Bob
+//! # ALTERNATIVE NAME 3: Carol
+//! 12: 1,{2,3}
+//! 3: 3,1,2
+//! ```
+//!
+//! The data type says whether ballots may contain ties (`toc`, `toi`) and
+//! whether they must rank every candidate (`soc`, `toc`). Empty ballots can't
+//! be represented and are left out when writing.
+use super::{count_ballots, BallotBuilder, Election, ParseError};
+
+fn parse_number(line_number: usize, token: &str, what: &str) -> Result<usize, ParseError> {
+    token
+        .trim()
+        .parse()
+        .map_err(|_| ParseError::new(line_number, format!("invalid {what} {:?}", token.trim())))
+}
+
+/// Parses a comma-separated order like `1,{2,3},4` into groups of 1-based
+/// candidate numbers.
+fn parse_order(line_number: usize, mut order: &str) -> Result<Vec<Vec<usize>>, ParseError> {
+    let mut groups = vec![];
+    loop {
+        order = order.trim_start();
+        let item;
+        (item, order) = match order.strip_prefix('{') {
+            Some(rest) => {
+                let end = rest
+                    .find('}')
+                    .ok_or_else(|| ParseError::new(line_number, "unclosed {"))?;
+                (&rest[..end], &rest[end + 1..])
+            }
+            None => order.split_at(order.find(',').unwrap_or(order.len())),
+        };
+        groups.push(
+            item.split(',')
+                .map(|candidate| parse_number(line_number, candidate, "alternative"))
+                .collect::<Result<Vec<_>, _>>()?,
+        );
+        order = order.trim_start();
+        if order.is_empty() {
+            return Ok(groups);
+        }
+        order = order
+            .strip_prefix(',')
+            .ok_or_else(|| ParseError::new(line_number, format!("expected , before {order:?}")))?;
+    }
+}
+
+pub fn parse(input: &str) -> Result<Election, ParseError> {
+    let mut title = None;
+    let mut data_type = None;
+    let mut num_choices = None;
+    let mut num_voters = None;
+    let mut names = vec![];
+    let mut ballots = vec![];
+    for (i, line) in input.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('#') {
+            let Some((key, value)) = header.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim() {
+                "TITLE" if !value.is_empty() => title = Some(value.to_owned()),
+                "DATA TYPE" => match value {
+                    "soc" | "soi" | "toc" | "toi" => data_type = Some(value.to_owned()),
+                    _ => {
+                        return Err(ParseError::new(
+                            line_number,
+                            format!("unsupported data type {value:?}"),
+                        ))
+                    }
+                },
+                "NUMBER ALTERNATIVES" => {
+                    num_choices = Some(parse_number(line_number, value, "number of alternatives")?)
+                }
+                "NUMBER VOTERS" => {
+                    num_voters = Some((
+                        line_number,
+                        parse_number(line_number, value, "number of voters")?,
+                    ))
+                }
+                key => {
+                    if let Some(candidate) = key.strip_prefix("ALTERNATIVE NAME ") {
+                        let candidate = parse_number(line_number, candidate, "alternative")?;
+                        names.push((line_number, candidate, value.to_owned()));
+                    }
+                }
+            }
+            continue;
+        }
+        let num_choices = num_choices
+            .ok_or_else(|| ParseError::new(line_number, "missing NUMBER ALTERNATIVES header"))?;
+        let (count, order) = line
+            .split_once(':')
+            .ok_or_else(|| ParseError::new(line_number, "expected count followed by :"))?;
+        let count = parse_number(line_number, count, "count")?;
+        let mut ballot = BallotBuilder::new(line_number, num_choices);
+        for group in parse_order(line_number, order)? {
+            let group = group
+                .into_iter()
+                .map(|candidate| {
+                    candidate.checked_sub(1).ok_or_else(|| {
+                        ParseError::new(line_number, "alternatives are numbered from 1")
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            ballot.push_group(&group)?;
+        }
+        match data_type.as_deref() {
+            Some("soc" | "soi") if ballot.has_ties() => {
+                return Err(ParseError::new(
+                    line_number,
+                    "ties are not allowed in strict orders (soc, soi)",
+                ))
+            }
+            Some("soc" | "toc") if ballot.ballot.len() != num_choices => {
+                return Err(ParseError::new(
+                    line_number,
+                    "every alternative must be ranked in complete orders (soc, toc)",
+                ))
+            }
+            _ => {}
+        }
+        ballot.finish(count, &mut ballots)?;
+    }
+
+    let num_choices = num_choices.ok_or_else(|| {
+        ParseError::new(input.lines().count(), "missing NUMBER ALTERNATIVES header")
+    })?;
+    if let Some((line_number, num_voters)) = num_voters {
+        if num_voters != ballots.len() {
+            return Err(ParseError::new(
+                line_number,
+                format!(
+                    "NUMBER VOTERS is {num_voters}, but there are {} ballots",
+                    ballots.len()
+                ),
+            ));
+        }
+    }
+    let mut candidates: Vec<String> = (1..=num_choices).map(|c| c.to_string()).collect();
+    for (line_number, candidate, name) in names {
+        match candidate.checked_sub(1).and_then(|c| candidates.get_mut(c)) {
+            Some(candidate) => *candidate = name,
+            None => {
+                return Err(ParseError::new(
+                    line_number,
+                    format!("unknown alternative {candidate}"),
+                ))
+            }
+        }
+    }
+    Ok(Election {
+        title,
+        candidates,
+        num_winners: 1,
+        ballots,
+    })
+}
+
+pub fn write(election: &Election) -> String {
+    let ballots = count_ballots(&election.ballots)
+        .into_iter()
+        .filter(|(_, groups)| !groups.is_empty())
+        .collect::<Vec<_>>();
+    let ties = ballots
+        .iter()
+        .any(|(_, groups)| groups.iter().any(|group| group.len() > 1));
+    let complete = ballots
+        .iter()
+        .all(|(_, groups)| groups.iter().map(Vec::len).sum::<usize>() == election.candidates.len());
+    let data_type = match (ties, complete) {
+        (false, true) => "soc",
+        (false, false) => "soi",
+        (true, true) => "toc",
+        (true, false) => "toi",
+    };
+
+    let mut output = String::new();
+    if let Some(title) = &election.title {
+        output += &format!("# TITLE: {title}\n");
+    }
+    output += &format!("# DATA TYPE: {data_type}\n");
+    output += &format!("# NUMBER ALTERNATIVES: {}\n", election.candidates.len());
+    output += &format!(
+        "# NUMBER VOTERS: {}\n",
+        ballots.iter().map(|(count, _)| count).sum::<usize>()
+    );
+    output += &format!("# NUMBER UNIQUE ORDERS: {}\n", ballots.len());
+    for (i, name) in election.candidates.iter().enumerate() {
+        output += &format!("# ALTERNATIVE NAME {}: {name}\n", i + 1);
+    }
+    for (count, groups) in ballots {
+        let order = groups
+            .iter()
+            .map(|group| {
+                let group = group
+                    .iter()
+                    .map(|candidate| (candidate + 1).to_string())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                if group.contains(',') {
+                    format!("{{{group}}}")
+                } else {
+                    group
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        output += &format!("{count}: {order}\n");
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::condorcet::{
+        test::{ballot, ballots},
+        VoteItem,
+    };
+    use crate::formats::test::{assert_same_election, election};
+
+    fn error(input: &str) -> String {
+        parse(input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut election = election();
+        election.ballots.retain(|ballot| !ballot.is_empty());
+        let written = write(&election);
+        assert_eq!(
+            written,
+            "# TITLE: Board election\n\
+             # DATA TYPE: toi\n\
+             # NUMBER ALTERNATIVES: 3\n\
+             # NUMBER VOTERS: 7\n\
+             # NUMBER UNIQUE ORDERS: 4\n\
+             # ALTERNATIVE NAME 1: Alice\n\
+             # ALTERNATIVE NAME 2: Bob Jones\n\
+             # ALTERNATIVE NAME 3: Carol\n\
+             2: 1,2,3\n\
+             1: {2,3},1\n\
+             1: 3\n\
+             3: 3,{1,2}\n"
+        );
+        assert_same_election(&parse(&written).unwrap(), &election);
+
+        election.ballots = ballots!((2: 0 > 1 > 2) (1: 2 > 1 > 0));
+        let written = write(&election);
+        assert!(written.contains("# DATA TYPE: soc\n"));
+        assert_same_election(&parse(&written).unwrap(), &election);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            error("1: 1,2\n"),
+            "line 1: missing NUMBER ALTERNATIVES header"
+        );
+        let header = "# DATA TYPE: soc\n# NUMBER ALTERNATIVES: 2\n";
+        assert_eq!(
+            error(&format!("{header}1 1,2\n")),
+            "line 3: expected count followed by :"
+        );
+        assert_eq!(
+            error(&format!("{header}1: 1,x\n")),
+            "line 3: invalid alternative \"x\""
+        );
+        assert_eq!(error(&format!("{header}1: 1,{{2\n")), "line 3: unclosed {");
+        assert_eq!(
+            error(&format!("{header}1: {{1,2}}3\n")),
+            "line 3: expected , before \"3\""
+        );
+        assert_eq!(
+            error(&format!("{header}1: 1,3\n")),
+            "line 3: unknown candidate 3"
+        );
+        assert_eq!(
+            error(&format!("{header}1: 0,1\n")),
+            "line 3: alternatives are numbered from 1"
+        );
+        assert_eq!(
+            error(&format!("{header}1: {{1,2}}\n")),
+            "line 3: ties are not allowed in strict orders (soc, soi)"
+        );
+        assert_eq!(
+            error(&format!("{header}1: 1\n")),
+            "line 3: every alternative must be ranked in complete orders (soc, toc)"
+        );
+        assert_eq!(
+            error(&format!("{header}# NUMBER VOTERS: 2\n1: 1,2\n")),
+            "line 3: NUMBER VOTERS is 2, but there are 1 ballots"
+        );
+        assert_eq!(
+            error(&format!("{header}# ALTERNATIVE NAME 3: C\n")),
+            "line 3: unknown alternative 3"
+        );
+        assert_eq!(
+            error("# DATA TYPE: cat\n"),
+            "line 1: unsupported data type \"cat\""
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+
+use crate::condorcet::{validate_ballots, VoteItem};
+
+/// Tolerance when comparing fractional vote counts.
+const EPSILON: f64 = 1e-9;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IrvRound {
+    /// counts[c] contains candidate c's first-preference votes this round, or
+    /// None if c was already eliminated. Ballots ranking several candidates
+    /// equally split their vote.
+    pub counts: Vec<Option<f64>>,
+    /// Votes for ballots that rank no remaining candidate.
+    pub exhausted: f64,
+    /// Candidates eliminated at the end of this round.
+    pub eliminated: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IrvTally {
+    pub rounds: Vec<IrvRound>,
+    // Ranks[0] contains the winner(s), the remaining ranks are in reverse
+    // order of elimination.
+    pub ranks: Vec<Vec<usize>>,
+}
+
+/// Find the most preferred continuing candidates on a sorted ballot.
+pub(crate) fn top_preferences(ballot: &[VoteItem], continuing: &[bool]) -> Vec<usize> {
+    let mut remaining = ballot.iter().filter(|item| continuing[item.candidate]);
+    let Some(first) = remaining.next() else {
+        return vec![];
+    };
+    std::iter::once(first)
+        .chain(remaining.take_while(|item| item.rank == first.rank))
+        .map(|item| item.candidate)
+        .collect()
+}
+
+/// Compute the results of an election using instant-runoff voting.
+///
+/// A ballot ranking several remaining candidates equally first splits its vote
+/// evenly between them. All candidates tied for the fewest votes are eliminated
+/// together. Elimination continues until every candidate is ranked, which
+/// never changes the winner.
+pub fn instant_runoff(num_choices: usize, votes: Vec<Vec<VoteItem>>) -> IrvTally {
+    let votes = validate_ballots(num_choices, votes);
+
+    let mut continuing = vec![true; num_choices];
+    let mut rounds = vec![];
+    let mut ranks = vec![];
+    while continuing.iter().any(|&c| c) {
+        let mut counts = vec![0.0; num_choices];
+        let mut exhausted = 0.0;
+        for ballot in votes.iter() {
+            let top = top_preferences(ballot, &continuing);
+            if top.is_empty() {
+                exhausted += 1.0;
+            }
+            for &c in top.iter() {
+                counts[c] += 1.0 / top.len() as f64;
+            }
+        }
+
+        let fewest = (0..num_choices)
+            .filter(|&c| continuing[c])
+            .map(|c| counts[c])
+            .fold(f64::INFINITY, f64::min);
+        let eliminated: Vec<usize> = (0..num_choices)
+            .filter(|&c| continuing[c] && counts[c] - fewest < EPSILON)
+            .collect();
+        let remaining: Vec<usize> = (0..num_choices).filter(|&c| continuing[c]).collect();
+        let counts = (0..num_choices)
+            .map(|c| continuing[c].then_some(counts[c]))
+            .collect();
+
+        if eliminated.len() == remaining.len() {
+            // Everyone left is tied, so they all win.
+            log::trace!("counts {counts:?} winners {remaining:?}");
+            rounds.push(IrvRound {
+                counts,
+                exhausted,
+                eliminated: vec![],
+            });
+            ranks.push(remaining);
+            break;
+        }
+        log::trace!("counts {counts:?} eliminating {eliminated:?}");
+        for &c in eliminated.iter() {
+            continuing[c] = false;
+        }
+        rounds.push(IrvRound {
+            counts,
+            exhausted,
+            eliminated: eliminated.clone(),
+        });
+        ranks.push(eliminated);
+    }
+    ranks.reverse();
+
+    IrvTally { rounds, ranks }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::condorcet::test::{ballot, ballots};
+
+    #[test]
+    fn test_instant_runoff() {
+        // https://en.wikipedia.org/wiki/Instant-runoff_voting#Examples
+        let tennessee = ballots!(
+            (42:0>1>2>3)
+            (26:1>2>3>0)
+            (15:2>3>1>0)
+            (17:3>2>1>0)
+        );
+        let tally = instant_runoff(4, tennessee);
+        assert_eq!(tally.ranks, vec![vec![3], vec![0], vec![1], vec![2]]);
+        assert_eq!(tally.rounds.len(), 4);
+        assert_eq!(
+            tally.rounds[0].counts,
+            vec![Some(42.0), Some(26.0), Some(15.0), Some(17.0)]
+        );
+        assert_eq!(tally.rounds[0].eliminated, vec![2]);
+        assert_eq!(
+            tally.rounds[1].counts,
+            vec![Some(42.0), Some(26.0), None, Some(32.0)]
+        );
+        assert_eq!(tally.rounds[1].eliminated, vec![1]);
+        assert_eq!(
+            tally.rounds[2].counts,
+            vec![Some(42.0), None, None, Some(58.0)]
+        );
+        assert_eq!(tally.rounds[3].counts, vec![None, None, None, Some(100.0)]);
+        assert!(tally.rounds[3].eliminated.is_empty());
+
+        assert_eq!(
+            instant_runoff(2, ballots!((1: 0 > 1) (1: 1 > 0))).ranks,
+            vec![vec![0, 1]]
+        );
+    }
+
+    #[test]
+    fn test_instant_runoff_equal_ranks() {
+        // Ballots ranking candidates equally split their vote.
+        let tally = instant_runoff(3, ballots!((2: 0 = 1 > 2) (1: 2) (1: 0)));
+        assert_eq!(
+            tally.rounds[0].counts,
+            vec![Some(2.0), Some(1.0), Some(1.0)]
+        );
+        assert_eq!(tally.rounds[0].eliminated, vec![1, 2]);
+        assert_eq!(tally.ranks, vec![vec![0], vec![1, 2]]);
+    }
+
+    #[test]
+    fn test_instant_runoff_partial_ballots() {
+        // Ballots with no remaining candidates are exhausted.
+        let tally = instant_runoff(3, ballots!((3: 0) (2: 1) (2: 2 > 1)));
+        assert_eq!(tally.rounds[0].eliminated, vec![1, 2]);
+        assert_eq!(tally.rounds[1].exhausted, 4.0);
+        assert_eq!(tally.ranks, vec![vec![0], vec![1, 2]]);
+
+        let tally = instant_runoff(3, ballots!((3: 0) (2: 1) (1: 2 > 1)));
+        assert_eq!(tally.rounds[0].eliminated, vec![2]);
+        assert_eq!(tally.rounds[1].counts, vec![Some(3.0), Some(3.0), None]);
+        assert_eq!(tally.ranks, vec![vec![0, 1], vec![2]]);
+    }
+}
@@ -0,0 +1,41 @@
+//! Ballot types, validation and tally methods shared by the decide server,
+//! its API and the decide-tally CLI.
+use serde::{Deserialize, Serialize};
+
+pub mod condorcet;
+pub mod formats;
+pub mod irv;
+pub mod robustness;
+pub mod score;
+pub mod stv;
+pub mod tiebreak;
+
+/// Method used to compute the results of a room.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TallyMethod {
+    #[default]
+    RankedPairs,
+    Schulze,
+    KemenyYoung,
+    InstantRunoff,
+    SingleTransferableVote,
+    Approval,
+    Star,
+}
+
+impl TallyMethod {
+    /// Highest score a ballot may give a candidate, or None if the method uses
+    /// ranked ballots.
+    pub fn max_score(self) -> Option<u64> {
+        match self {
+            Self::RankedPairs
+            | Self::Schulze
+            | Self::KemenyYoung
+            | Self::InstantRunoff
+            | Self::SingleTransferableVote => None,
+            Self::Approval => Some(1),
+            Self::Star => Some(5),
+        }
+    }
+}
@@ -0,0 +1,299 @@
+//! How many ballots it would take to change the winners under ranked pairs.
+//!
+//! Finding the exact minimum is hard in general, so each margin is found by a
+//! fixed strategy for every challenger: adding ballots that rank only the
+//! challenger, or rewriting ballots to rank the challenger first and the
+//! current winners last. The margins are therefore upper bounds.
+//!
+//! Each rewritten ballot means another tally, so at most
+//! `MAX_BALLOTS_TO_CHANGE` ballots are rewritten across all challengers, and
+//! rooms with more than `MAX_CHOICES` candidates aren't searched at all.
+use serde::{Deserialize, Serialize};
+
+use crate::condorcet::{
+    add_ballot, ranked_pairs_from_totals, remove_ballot, validate_ballots, VoteItem,
+};
+
+/// Most ballots rewritten, across all challengers, before giving up.
+pub const MAX_BALLOTS_TO_CHANGE: u64 = 2_000;
+
+/// Most candidates a room may have for its robustness to be found.
+pub const MAX_CHOICES: usize = 50;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Margin {
+    /// Number of ballots needed.
+    pub ballots: u64,
+    /// The candidate those ballots rank first.
+    pub challenger: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Robustness {
+    /// Extra ballots that change the winners, an upper bound on the fewest
+    /// needed. None if there is nobody to challenge the winners, e.g. with a
+    /// single candidate.
+    pub ballots_to_add: Option<Margin>,
+    /// Existing ballots that, changed, change the winners, an upper bound on
+    /// the fewest needed. None if changing every ballot is not enough, e.g.
+    /// with no ballots, or if the search rewrote `MAX_BALLOTS_TO_CHANGE`
+    /// ballots without finding it.
+    pub ballots_to_change: Option<Margin>,
+}
+
+fn ranked_pairs_winners(totals: &[Vec<u64>]) -> Vec<usize> {
+    let mut winners = ranked_pairs_from_totals(totals.to_vec())
+        .ranks
+        .into_iter()
+        .next()
+        .unwrap_or_default();
+    winners.sort();
+    winners
+}
+
+/// A number of ballots ranking only the challenger that change the winners.
+/// The search assumes the winners stay changed once they change, which
+/// usually holds but isn't guaranteed, so this is an upper bound on the
+/// fewest needed.
+fn ballots_to_add(totals: &[Vec<u64>], winners: &[usize], challenger: usize) -> u64 {
+    let with_ballots = |count: u64| {
+        let mut totals = totals.to_vec();
+        for (c, total) in totals[challenger].iter_mut().enumerate() {
+            if c != challenger {
+                *total += count;
+            }
+        }
+        totals
+    };
+    // With this many, the challenger beats everyone and wins outright.
+    let mut high = (0..totals.len())
+        .map(|c| totals[c][challenger].saturating_sub(totals[challenger][c]))
+        .max()
+        .unwrap_or(0)
+        + 1;
+    // Binary search between one ballot and enough to win outright. If the
+    // winners change and then change back as ballots are added, this finds
+    // some count that changes them rather than the smallest.
+    let mut low = 1;
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if ranked_pairs_winners(&with_ballots(mid)) != winners {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+    low
+}
+
+/// Rank the challenger first and the current winners last, keeping the order
+/// of everyone else. Returns None if the ballot already does.
+fn rewrite_ballot(
+    ballot: &[VoteItem],
+    winners: &[usize],
+    challenger: usize,
+) -> Option<Vec<VoteItem>> {
+    let mut rewritten = vec![VoteItem {
+        candidate: challenger,
+        rank: 0,
+    }];
+    let others = ballot
+        .iter()
+        .filter(|item| item.candidate != challenger && !winners.contains(&item.candidate));
+    let demoted = ballot
+        .iter()
+        .filter(|item| item.candidate != challenger && winners.contains(&item.candidate));
+    rewritten.extend(others.map(|item| VoteItem {
+        candidate: item.candidate,
+        rank: item.rank.saturating_add(1),
+    }));
+    rewritten.extend(demoted.map(|item| VoteItem {
+        candidate: item.candidate,
+        rank: u64::MAX,
+    }));
+    let already_rewritten = ballot
+        .first()
+        .is_some_and(|item| item.candidate == challenger)
+        && ballot.iter().skip(1).all(|item| item.rank > ballot[0].rank)
+        && ballot
+            .iter()
+            .skip_while(|item| !winners.contains(&item.candidate))
+            .all(|item| winners.contains(&item.candidate));
+    (!already_rewritten).then_some(rewritten)
+}
+
+/// Fewest ballots that, rewritten for the challenger, change the winners.
+/// Ballots ranking the winners highest are rewritten first. Gives up after
+/// rewriting `max_changed` ballots, or once `budget` runs out; each rewrite
+/// takes one from it.
+fn ballots_to_change(
+    totals: &[Vec<u64>],
+    votes: &[Vec<VoteItem>],
+    winners: &[usize],
+    challenger: usize,
+    max_changed: u64,
+    budget: &mut u64,
+) -> Option<u64> {
+    let winner_position = |ballot: &Vec<VoteItem>| {
+        ballot
+            .iter()
+            .position(|item| winners.contains(&item.candidate))
+            .unwrap_or(ballot.len())
+    };
+    let mut votes: Vec<&Vec<VoteItem>> = votes.iter().collect();
+    votes.sort_by_key(|ballot| winner_position(ballot));
+    let mut totals = totals.to_vec();
+    let mut changed = 0;
+    for ballot in votes {
+        if changed == max_changed || *budget == 0 {
+            return None;
+        }
+        let Some(rewritten) = rewrite_ballot(ballot, winners, challenger) else {
+            continue;
+        };
+        remove_ballot(&mut totals, ballot);
+        add_ballot(&mut totals, &rewritten);
+        changed += 1;
+        *budget -= 1;
+        if ranked_pairs_winners(&totals) != winners {
+            return Some(changed);
+        }
+    }
+    None
+}
+
+/// Find how many ballots it would take to change the ranked pairs winners.
+/// `totals` is the pairwise matrix of `votes`. None if there are more than
+/// `MAX_CHOICES` candidates.
+pub fn robustness(totals: &[Vec<u64>], votes: Vec<Vec<VoteItem>>) -> Option<Robustness> {
+    let num_choices = totals.len();
+    if num_choices > MAX_CHOICES {
+        return None;
+    }
+    let votes = validate_ballots(num_choices, votes);
+    let winners = ranked_pairs_winners(totals);
+    // A co-winner can challenge the others by breaking the tie.
+    let challengers = (0..num_choices).filter(|&c| winners != [c]);
+    let mut result = Robustness {
+        ballots_to_add: None,
+        ballots_to_change: None,
+    };
+    let mut budget = MAX_BALLOTS_TO_CHANGE;
+    for challenger in challengers {
+        let added = ballots_to_add(totals, &winners, challenger);
+        if result
+            .ballots_to_add
+            .as_ref()
+            .is_none_or(|m| added < m.ballots)
+        {
+            result.ballots_to_add = Some(Margin {
+                ballots: added,
+                challenger,
+            });
+        }
+        // Only a challenger needing fewer ballots than the best so far
+        // matters, so stop searching before that.
+        let max_changed = result
+            .ballots_to_change
+            .as_ref()
+            .map_or(u64::MAX, |m| m.ballots - 1);
+        if let Some(changed) = ballots_to_change(
+            totals,
+            &votes,
+            &winners,
+            challenger,
+            max_changed,
+            &mut budget,
+        ) {
+            result.ballots_to_change = Some(Margin {
+                ballots: changed,
+                challenger,
+            });
+        }
+    }
+    Some(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::condorcet::{
+        pairwise_totals,
+        test::{ballot, ballots},
+    };
+
+    fn robustness(num_choices: usize, votes: Vec<Vec<VoteItem>>) -> Robustness {
+        super::robustness(&pairwise_totals(num_choices, votes.clone()), votes).unwrap()
+    }
+
+    fn margin(ballots: u64, challenger: usize) -> Option<Margin> {
+        Some(Margin {
+            ballots,
+            challenger,
+        })
+    }
+
+    #[test]
+    fn test_robustness() {
+        // A one-vote win.
+        assert_eq!(
+            robustness(2, ballots!((3: 0 > 1) (2: 1 > 0))),
+            Robustness {
+                ballots_to_add: margin(1, 1),
+                ballots_to_change: margin(1, 1),
+            }
+        );
+        // A unanimous win takes as many new ballots to tie, and a majority
+        // of changed ballots to overturn.
+        assert_eq!(
+            robustness(3, ballots!((5: 0 > 1 > 2))),
+            Robustness {
+                ballots_to_add: margin(5, 1),
+                ballots_to_change: margin(3, 1),
+            }
+        );
+        // Any ballot breaks a tie.
+        assert_eq!(
+            robustness(3, vec![]),
+            Robustness {
+                ballots_to_add: margin(1, 0),
+                ballots_to_change: None,
+            }
+        );
+        assert_eq!(
+            robustness(1, ballots!((2: 0))),
+            Robustness {
+                ballots_to_add: None,
+                ballots_to_change: None,
+            }
+        );
+        // Past the cap, the changed ballots aren't counted. Rooms with too
+        // many candidates aren't searched at all.
+        let landslide = vec![ballot!(0 > 1); 2 * MAX_BALLOTS_TO_CHANGE as usize + 2];
+        assert_eq!(
+            robustness(2, landslide),
+            Robustness {
+                ballots_to_add: margin(2 * MAX_BALLOTS_TO_CHANGE + 2, 1),
+                ballots_to_change: None,
+            }
+        );
+        assert_eq!(
+            super::robustness(&vec![vec![0; MAX_CHOICES + 1]; MAX_CHOICES + 1], vec![]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_rewrite_ballot() {
+        let rewritten = rewrite_ballot(&ballot!(0 > 1 = 2 > 3), &[0], 3).unwrap();
+        assert_eq!(
+            rewritten
+                .iter()
+                .map(|item| (item.candidate, item.rank))
+                .collect::<Vec<_>>(),
+            vec![(3, 0), (1, 3), (2, 3), (0, u64::MAX)]
+        );
+        assert!(rewrite_ballot(&ballot!(3 > 1 > 0), &[0], 3).is_none());
+        assert!(rewrite_ballot(&ballot!(3 = 1 > 0), &[0], 3).is_some());
+    }
+}
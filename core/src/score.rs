@@ -0,0 +1,227 @@
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::condorcet::VoteItem;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScoreItem {
+    pub candidate: usize,
+    // Higher is better.
+    pub score: u64,
+}
+
+/// The automatic runoff between the two highest scoring candidates in STAR.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StarRunoff {
+    pub finalists: [usize; 2],
+    /// preferred[i] contains the number of ballots scoring finalists[i] higher
+    /// than the other finalist.
+    pub preferred: [u64; 2],
+    /// Ballots scoring both finalists equally.
+    pub no_preference: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoreTally {
+    /// totals[c] contains the sum of candidate c's scores.
+    pub totals: Vec<u64>,
+    /// Present if the room uses STAR voting.
+    pub runoff: Option<StarRunoff>,
+    // Ranks[0] contains the winner(s), followed by the remaining candidates
+    // grouped from best to worst.
+    pub ranks: Vec<Vec<usize>>,
+}
+
+/// Filter invalid and duplicate candidates from ballots.
+pub fn validate_score_ballots(
+    num_choices: usize,
+    votes: Vec<Vec<ScoreItem>>,
+) -> Vec<Vec<ScoreItem>> {
+    votes
+        .into_iter()
+        .map(|mut ballot| {
+            let mut seen_candidates = vec![false; num_choices];
+            ballot.retain(|item| {
+                item.candidate < num_choices
+                    && !std::mem::replace(&mut seen_candidates[item.candidate], true)
+            });
+            ballot
+        })
+        .collect()
+}
+
+/// Convert a score ballot to a ranked ballot, e.g. to compute a pairwise
+/// matrix. Candidates without a score are left unranked.
+pub fn ranked_ballot(ballot: &[ScoreItem]) -> Vec<VoteItem> {
+    ballot
+        .iter()
+        .map(|item| VoteItem {
+            candidate: item.candidate,
+            rank: u64::MAX - item.score,
+        })
+        .collect()
+}
+
+/// Convert score ballots to ranked ballots.
+pub fn ranked_ballots(votes: &[Vec<ScoreItem>]) -> Vec<Vec<VoteItem>> {
+    votes.iter().map(|ballot| ranked_ballot(ballot)).collect()
+}
+
+/// Group candidates from highest to lowest total.
+fn rank_by_totals(candidates: impl Iterator<Item = usize>, totals: &[u64]) -> Vec<Vec<usize>> {
+    candidates
+        .sorted_by_key(|&c| std::cmp::Reverse(totals[c]))
+        .chunk_by(|&c| totals[c])
+        .into_iter()
+        .map(|(_total, rank)| rank.collect())
+        .collect()
+}
+
+/// Compute the results of an election using approval voting.
+/// Any positive score counts as an approval.
+pub fn approval(num_choices: usize, votes: Vec<Vec<ScoreItem>>) -> ScoreTally {
+    let mut totals = vec![0; num_choices];
+    for ballot in validate_score_ballots(num_choices, votes) {
+        for item in ballot {
+            totals[item.candidate] += item.score.min(1);
+        }
+    }
+    let ranks = rank_by_totals(0..num_choices, &totals);
+    ScoreTally {
+        totals,
+        runoff: None,
+        ranks,
+    }
+}
+
+/// Compute the results of an election using STAR voting (score then automatic
+/// runoff).
+///
+/// The two candidates with the highest total scores advance to a runoff, which
+/// is won by whichever finalist more ballots score higher. Ties for a finalist
+/// spot go to the candidate listed first; a tied runoff goes to the finalist
+/// with the higher total score.
+pub fn star(num_choices: usize, votes: Vec<Vec<ScoreItem>>) -> ScoreTally {
+    // See https://en.wikipedia.org/wiki/STAR_voting
+
+    let votes = validate_score_ballots(num_choices, votes);
+    let mut totals = vec![0; num_choices];
+    for ballot in votes.iter() {
+        for item in ballot.iter() {
+            totals[item.candidate] += item.score;
+        }
+    }
+
+    let by_total: Vec<usize> = (0..num_choices)
+        .sorted_by_key(|&c| std::cmp::Reverse(totals[c]))
+        .collect();
+    let &[a, b, ..] = by_total.as_slice() else {
+        let ranks = rank_by_totals(0..num_choices, &totals);
+        return ScoreTally {
+            totals,
+            runoff: None,
+            ranks,
+        };
+    };
+
+    let mut preferred = [0, 0];
+    let mut no_preference = 0;
+    for ballot in votes.iter() {
+        let score = |c| {
+            ballot
+                .iter()
+                .find(|item| item.candidate == c)
+                .map_or(0, |item| item.score)
+        };
+        match score(a).cmp(&score(b)) {
+            std::cmp::Ordering::Greater => preferred[0] += 1,
+            std::cmp::Ordering::Less => preferred[1] += 1,
+            std::cmp::Ordering::Equal => no_preference += 1,
+        }
+    }
+    log::trace!("runoff {a} vs {b}: {preferred:?}, {no_preference} no preference");
+
+    let finalist_ranks = match (preferred[0].cmp(&preferred[1]), totals[a].cmp(&totals[b])) {
+        (std::cmp::Ordering::Less, _) => vec![vec![b], vec![a]],
+        (std::cmp::Ordering::Equal, std::cmp::Ordering::Equal) => vec![vec![a, b]],
+        _ => vec![vec![a], vec![b]],
+    };
+    let ranks = finalist_ranks
+        .into_iter()
+        .chain(rank_by_totals(by_total[2..].iter().cloned(), &totals))
+        .collect();
+
+    ScoreTally {
+        totals,
+        runoff: Some(StarRunoff {
+            finalists: [a, b],
+            preferred,
+            no_preference,
+        }),
+        ranks,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ballots(ballots: &[(usize, &[u64])]) -> Vec<Vec<ScoreItem>> {
+        ballots
+            .iter()
+            .flat_map(|&(num, scores)| {
+                (0..num).map(move |_| {
+                    scores
+                        .iter()
+                        .enumerate()
+                        .map(|(candidate, &score)| ScoreItem { candidate, score })
+                        .collect()
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_approval() {
+        let tally = approval(
+            4,
+            ballots(&[(3, &[1, 1, 0, 0]), (2, &[0, 1, 0, 1]), (1, &[0, 0, 0, 1])]),
+        );
+        assert_eq!(tally.totals, vec![3, 5, 0, 3]);
+        assert_eq!(tally.ranks, vec![vec![1], vec![0, 3], vec![2]]);
+        assert!(tally.runoff.is_none());
+    }
+
+    #[test]
+    fn test_star() {
+        // https://en.wikipedia.org/wiki/STAR_voting#Example
+        let tennessee = ballots(&[
+            (42, &[5, 2, 1, 0]),
+            (26, &[0, 5, 3, 2]),
+            (15, &[0, 3, 5, 4]),
+            (17, &[0, 2, 4, 5]),
+        ]);
+        let tally = star(4, tennessee);
+        assert_eq!(tally.totals, vec![210, 293, 263, 197]);
+        let runoff = tally.runoff.unwrap();
+        assert_eq!(runoff.finalists, [1, 2]);
+        assert_eq!(runoff.preferred, [68, 32]);
+        assert_eq!(runoff.no_preference, 0);
+        assert_eq!(tally.ranks, vec![vec![1], vec![2], vec![0], vec![3]]);
+
+        // The runoff can overturn the score totals.
+        let tally = star(3, ballots(&[(2, &[5, 4, 0]), (1, &[0, 5, 0])]));
+        assert_eq!(tally.totals, vec![10, 13, 0]);
+        assert_eq!(tally.ranks, vec![vec![0], vec![1], vec![2]]);
+
+        // A tied runoff is decided by score, then shared.
+        let tally = star(2, ballots(&[(1, &[5, 0]), (1, &[0, 4])]));
+        assert_eq!(tally.ranks, vec![vec![0], vec![1]]);
+        let tally = star(2, ballots(&[(1, &[5, 0]), (1, &[0, 5])]));
+        assert_eq!(tally.ranks, vec![vec![0, 1]]);
+
+        let tally = star(1, ballots(&[(1, &[3])]));
+        assert_eq!(tally.ranks, vec![vec![0]]);
+        assert!(tally.runoff.is_none());
+    }
+}
@@ -0,0 +1,280 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    condorcet::{validate_ballots, VoteItem},
+    irv::top_preferences,
+};
+
+/// Tolerance when comparing fractional vote counts.
+const EPSILON: f64 = 1e-9;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StvRound {
+    /// counts[c] contains candidate c's votes at the start of this round, or
+    /// None if c was already eliminated. Elected candidates keep the quota.
+    pub counts: Vec<Option<f64>>,
+    /// Votes for ballots that rank no remaining candidate.
+    pub exhausted: f64,
+    /// Candidates elected this round.
+    pub elected: Vec<usize>,
+    /// Candidates eliminated this round.
+    pub eliminated: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StvTally {
+    /// Votes needed to be elected (the Droop quota).
+    pub quota: f64,
+    /// Elected candidates, in order of election.
+    pub elected: Vec<usize>,
+    pub rounds: Vec<StvRound>,
+    // Ranks[0] contains the elected candidates, then any candidates that were
+    // neither elected nor eliminated, then the rest in reverse order of
+    // elimination.
+    pub ranks: Vec<Vec<usize>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Hopeful,
+    Elected,
+    Eliminated,
+}
+
+/// A (fraction of a) ballot currently counting towards a candidate.
+struct Parcel {
+    ballot: usize,
+    weight: f64,
+}
+
+/// Assign a parcel to its most preferred hopeful candidates, splitting it
+/// evenly between equally ranked candidates.
+fn distribute(
+    parcel: Parcel,
+    votes: &[Vec<VoteItem>],
+    status: &[Status],
+    piles: &mut [Vec<Parcel>],
+    exhausted: &mut f64,
+) {
+    let hopeful: Vec<bool> = status.iter().map(|&s| s == Status::Hopeful).collect();
+    let top = top_preferences(&votes[parcel.ballot], &hopeful);
+    if top.is_empty() {
+        *exhausted += parcel.weight;
+    }
+    for &c in top.iter() {
+        piles[c].push(Parcel {
+            ballot: parcel.ballot,
+            weight: parcel.weight / top.len() as f64,
+        });
+    }
+}
+
+/// Compute the results of a multi-winner election using the single
+/// transferable vote with a Droop quota.
+///
+/// Surpluses are transferred with the Gregory method: every ballot counting
+/// towards an elected candidate moves on to its next preference, at a weight
+/// scaled down so that the candidate keeps exactly the quota. Ties for last
+/// place are broken by the most recent round in which the candidates' counts
+/// differed, then by eliminating the candidate listed last.
+pub fn single_transferable_vote(
+    num_choices: usize,
+    num_winners: usize,
+    votes: Vec<Vec<VoteItem>>,
+) -> StvTally {
+    // See https://en.wikipedia.org/wiki/Counting_single_transferable_votes
+
+    let votes = validate_ballots(num_choices, votes);
+    let num_winners = num_winners.min(num_choices);
+    let num_valid = votes.iter().filter(|ballot| !ballot.is_empty()).count();
+    let quota = (num_valid / (num_winners + 1) + 1) as f64;
+
+    let mut status = vec![Status::Hopeful; num_choices];
+    let mut piles: Vec<Vec<Parcel>> = (0..num_choices).map(|_| vec![]).collect();
+    let mut exhausted = 0.0;
+    for ballot in 0..votes.len() {
+        let parcel = Parcel {
+            ballot,
+            weight: 1.0,
+        };
+        distribute(parcel, &votes, &status, &mut piles, &mut exhausted);
+    }
+
+    let mut rounds: Vec<StvRound> = vec![];
+    let mut elected = vec![];
+    let mut eliminated = vec![];
+    while elected.len() < num_winners {
+        let hopeful: Vec<usize> = (0..num_choices)
+            .filter(|&c| status[c] == Status::Hopeful)
+            .collect();
+        if hopeful.is_empty() {
+            break;
+        }
+        let totals: Vec<f64> = piles
+            .iter()
+            .map(|pile| pile.iter().map(|parcel| parcel.weight).sum())
+            .collect();
+        let counts = (0..num_choices)
+            .map(|c| match status[c] {
+                Status::Hopeful => Some(totals[c]),
+                Status::Elected => Some(quota),
+                Status::Eliminated => None,
+            })
+            .collect();
+        log::trace!("counts {counts:?} exhausted {exhausted}");
+
+        if hopeful.len() <= num_winners - elected.len() {
+            // Everyone left fills the remaining seats.
+            for &c in hopeful.iter() {
+                status[c] = Status::Elected;
+            }
+            rounds.push(StvRound {
+                counts,
+                exhausted,
+                elected: hopeful.clone(),
+                eliminated: vec![],
+            });
+            elected.extend(hopeful);
+            break;
+        }
+
+        let best = hopeful
+            .iter()
+            .cloned()
+            .reduce(|a, b| if totals[b] > totals[a] { b } else { a })
+            .unwrap();
+        if totals[best] > quota - EPSILON {
+            log::trace!("electing {best}");
+            status[best] = Status::Elected;
+            elected.push(best);
+            rounds.push(StvRound {
+                counts,
+                exhausted,
+                elected: vec![best],
+                eliminated: vec![],
+            });
+            let transfer_value = (totals[best] - quota).max(0.0) / totals[best];
+            for mut parcel in std::mem::take(&mut piles[best]) {
+                parcel.weight *= transfer_value;
+                distribute(parcel, &votes, &status, &mut piles, &mut exhausted);
+            }
+        } else {
+            let fewest = hopeful
+                .iter()
+                .map(|&c| totals[c])
+                .fold(f64::INFINITY, f64::min);
+            let mut tied: Vec<usize> = hopeful
+                .into_iter()
+                .filter(|&c| totals[c] - fewest < EPSILON)
+                .collect();
+            for round in rounds.iter().rev() {
+                let count = |c: usize| round.counts[c].unwrap_or(0.0);
+                let fewest = tied.iter().map(|&c| count(c)).fold(f64::INFINITY, f64::min);
+                tied.retain(|&c| count(c) - fewest < EPSILON);
+            }
+            let worst = *tied.last().unwrap();
+            log::trace!("eliminating {worst}");
+            status[worst] = Status::Eliminated;
+            eliminated.push(worst);
+            rounds.push(StvRound {
+                counts,
+                exhausted,
+                elected: vec![],
+                eliminated: vec![worst],
+            });
+            for parcel in std::mem::take(&mut piles[worst]) {
+                distribute(parcel, &votes, &status, &mut piles, &mut exhausted);
+            }
+        }
+    }
+
+    let unelected: Vec<usize> = (0..num_choices)
+        .filter(|&c| status[c] == Status::Hopeful)
+        .collect();
+    let ranks = std::iter::once(elected.clone())
+        .chain(std::iter::once(unelected))
+        .chain(eliminated.into_iter().rev().map(|c| vec![c]))
+        .filter(|rank| !rank.is_empty())
+        .collect();
+
+    StvTally {
+        quota,
+        elected,
+        rounds,
+        ranks,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::condorcet::test::{ballot, ballots};
+
+    #[test]
+    fn test_single_transferable_vote() {
+        // https://en.wikipedia.org/wiki/Single_transferable_vote#Example
+        let food = ballots!(
+            (4:0)
+            (2:1>0)
+            (8:2>3)
+            (4:2>4)
+            (1:3)
+            (1:4)
+        );
+        let tally = single_transferable_vote(5, 3, food);
+        assert_eq!(tally.quota, 6.0);
+        assert_eq!(tally.elected, vec![2, 0, 3]);
+        assert_eq!(tally.ranks, vec![vec![2, 0, 3], vec![4], vec![1]]);
+        assert_eq!(
+            tally.rounds[0].counts,
+            vec![Some(4.0), Some(2.0), Some(12.0), Some(1.0), Some(1.0)]
+        );
+        assert_eq!(tally.rounds[0].elected, vec![2]);
+        assert_eq!(
+            tally.rounds[1].counts,
+            vec![Some(4.0), Some(2.0), Some(6.0), Some(5.0), Some(3.0)]
+        );
+        assert_eq!(tally.rounds[1].eliminated, vec![1]);
+        assert_eq!(tally.rounds[2].elected, vec![0]);
+        assert_eq!(tally.rounds[3].eliminated, vec![4]);
+        assert_eq!(tally.rounds[4].exhausted, 3.0);
+        assert_eq!(tally.rounds[4].elected, vec![3]);
+    }
+
+    #[test]
+    fn test_single_transferable_vote_single_winner() {
+        // With one seat, STV is instant-runoff voting.
+        let tennessee = ballots!(
+            (42:0>1>2>3)
+            (26:1>2>3>0)
+            (15:2>3>1>0)
+            (17:3>2>1>0)
+        );
+        let tally = single_transferable_vote(4, 1, tennessee);
+        assert_eq!(tally.quota, 51.0);
+        assert_eq!(tally.elected, vec![3]);
+        assert_eq!(tally.ranks, vec![vec![3], vec![0], vec![1], vec![2]]);
+    }
+
+    #[test]
+    fn test_single_transferable_vote_ties() {
+        // Ties for last place are broken by earlier rounds.
+        let tally = single_transferable_vote(4, 1, ballots!((5: 0) (2: 1) (3: 2) (1: 3 > 1)));
+        assert_eq!(tally.rounds[0].eliminated, vec![3]);
+        assert_eq!(
+            tally.rounds[1].counts,
+            vec![Some(5.0), Some(3.0), Some(3.0), None]
+        );
+        assert_eq!(tally.rounds[1].eliminated, vec![1]);
+        assert_eq!(tally.elected, vec![0]);
+
+        // Otherwise, the last candidate is eliminated.
+        let tally = single_transferable_vote(3, 1, ballots!((3: 0) (2: 2 > 1) (2: 1 > 2)));
+        assert_eq!(tally.rounds[0].eliminated, vec![2]);
+        assert_eq!(tally.elected, vec![1]);
+
+        // More seats than candidates.
+        let tally = single_transferable_vote(2, 3, ballots!((1: 0) (1: 1)));
+        assert_eq!(tally.elected, vec![0, 1]);
+    }
+}
@@ -0,0 +1,111 @@
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::condorcet::VoteItem;
+
+/// How to order candidates that the tally method ranks equally.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TieBreak {
+    /// Report ties as they are.
+    #[default]
+    None,
+    /// Order tied candidates by a ballot drawn at random.
+    RandomBallot,
+    /// Draw lots.
+    Lottery,
+    /// Send two tied winners to play rock paper scissors. Ties between more
+    /// winners are broken by lottery instead.
+    RockPaperScissors,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TieBreakResult {
+    pub method: TieBreak,
+    /// Seed for all random draws. Publishing it makes the order reproducible.
+    pub seed: u64,
+    /// Every candidate from best to worst, with ties broken.
+    /// None if ties are settled by rock paper scissors.
+    pub order: Option<Vec<usize>>,
+    /// Rock paper scissors room for the tied winners, if two are tied.
+    pub rps_room: Option<String>,
+}
+
+/// Order tied candidates within each rank by `key`, shuffling candidates with
+/// equal keys. Returns every candidate from best to worst.
+fn break_ties<K: Ord>(
+    ranks: &[Vec<usize>],
+    key: impl Fn(usize) -> K,
+    rng: &mut ChaCha8Rng,
+) -> Vec<usize> {
+    ranks
+        .iter()
+        .flat_map(|rank| {
+            let mut rank = rank.clone();
+            rank.shuffle(rng);
+            rank.sort_by_key(|&c| key(c));
+            rank
+        })
+        .collect()
+}
+
+/// Break ties by drawing lots.
+/// The same seed always produces the same order.
+pub fn lottery(ranks: &[Vec<usize>], seed: u64) -> Vec<usize> {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    break_ties(ranks, |_| (), &mut rng)
+}
+
+/// Break ties using the preferences of a randomly chosen ballot. Candidates
+/// that ballot ranks equally or leaves unranked are ordered by lottery.
+/// The same seed and ballots, in the same order, always produce the same order.
+pub fn random_ballot(ranks: &[Vec<usize>], votes: &[Vec<VoteItem>], seed: u64) -> Vec<usize> {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let ballot: &[VoteItem] = if votes.is_empty() {
+        &[]
+    } else {
+        &votes[rng.random_range(0..votes.len())]
+    };
+    let rank_on_ballot = |c: usize| {
+        ballot
+            .iter()
+            .find(|item| item.candidate == c)
+            .map_or(u64::MAX, |item| item.rank)
+    };
+    break_ties(ranks, rank_on_ballot, &mut rng)
+}
+
+#[cfg(test)]
+mod test {
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::condorcet::test::{ballot, ballots};
+
+    #[test]
+    fn test_lottery() {
+        let ranks = vec![vec![0, 1, 2], vec![3], vec![4, 5]];
+        let order = lottery(&ranks, 42);
+        assert_eq!(order, lottery(&ranks, 42));
+        assert_eq!(order[..3].iter().sorted().collect::<Vec<_>>(), [&0, &1, &2]);
+        assert_eq!(order[3], 3);
+        assert_eq!(order[4..].iter().sorted().collect::<Vec<_>>(), [&4, &5]);
+        assert_eq!(lottery(&[vec![0], vec![1]], 42), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_random_ballot() {
+        let ranks = vec![vec![0, 1, 2], vec![3]];
+        // Every ballot agrees, so the draw doesn't matter.
+        let votes = ballots!((3: 2 > 0 > 1 > 3));
+        for seed in 0..10 {
+            assert_eq!(random_ballot(&ranks, &votes, seed), vec![2, 0, 1, 3]);
+        }
+        // Candidates the ballot leaves unranked come last.
+        let votes = ballots!((1: 1));
+        assert_eq!(random_ballot(&ranks, &votes, 0)[0], 1);
+        // Without ballots, this is a lottery.
+        assert_eq!(random_ballot(&ranks, &[], 7), lottery(&ranks, 7));
+    }
+}
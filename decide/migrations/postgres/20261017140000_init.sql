@@ -0,0 +1,24 @@
+CREATE TABLE room (
+  id TEXT PRIMARY KEY,
+  state JSONB NOT NULL,
+  last_active TIMESTAMPTZ NOT NULL DEFAULT now(),
+  -- Incremented on every write, so writers can detect concurrent changes.
+  version BIGINT NOT NULL DEFAULT 0
+);
+
+CREATE TABLE candidate (
+  room_id TEXT NOT NULL REFERENCES room (id) ON DELETE CASCADE,
+  idx BIGINT NOT NULL,
+  name TEXT NOT NULL,
+  PRIMARY KEY (room_id, idx)
+);
+
+CREATE TABLE ballot (
+  room_id TEXT NOT NULL REFERENCES room (id) ON DELETE CASCADE,
+  client_id TEXT NOT NULL,
+  name TEXT NOT NULL,
+  selections JSONB NOT NULL,
+  scores JSONB NOT NULL,
+  submitted TIMESTAMPTZ NOT NULL DEFAULT now(),
+  PRIMARY KEY (room_id, client_id)
+);
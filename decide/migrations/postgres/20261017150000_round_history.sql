@@ -0,0 +1,8 @@
+-- Finished rounds of voting, kept when a room starts a new round. Rows are
+-- only ever inserted, so past results never change.
+CREATE TABLE round (
+  room_id TEXT NOT NULL REFERENCES room (id) ON DELETE CASCADE,
+  idx BIGINT NOT NULL,
+  round JSONB NOT NULL,
+  PRIMARY KEY (room_id, idx)
+);
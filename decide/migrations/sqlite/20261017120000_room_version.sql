@@ -0,0 +1,2 @@
+-- Incremented on every write, so writers can detect concurrent changes.
+ALTER TABLE room ADD COLUMN version INTEGER NOT NULL DEFAULT 0;
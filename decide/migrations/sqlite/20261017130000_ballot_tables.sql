@@ -0,0 +1,42 @@
+-- Move choices and votes out of the room state JSON into their own tables,
+-- so that each vote writes a single ballot row.
+CREATE TABLE candidate (
+  room_id TEXT NOT NULL REFERENCES room (id) ON DELETE CASCADE,
+  idx INTEGER NOT NULL,
+  name TEXT NOT NULL,
+  PRIMARY KEY (room_id, idx)
+);
+
+CREATE TABLE ballot (
+  room_id TEXT NOT NULL REFERENCES room (id) ON DELETE CASCADE,
+  client_id TEXT NOT NULL,
+  name TEXT NOT NULL,
+  selections JSON NOT NULL,
+  scores JSON NOT NULL,
+  submitted DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (room_id, client_id)
+);
+
+-- Room states are tagged V1 or V2; both hold choices and votes.
+INSERT INTO candidate (room_id, idx, name)
+SELECT room.id, choice.key, choice.value
+FROM room,
+  json_each(COALESCE(json_extract(room.state, '$.V2'), json_extract(room.state, '$.V1')), '$.choices') AS choice;
+
+INSERT INTO ballot (room_id, client_id, name, selections, scores)
+SELECT
+  room.id,
+  vote.key,
+  json_extract(vote.value, '$.name'),
+  json(json_extract(vote.value, '$.selections')),
+  json(COALESCE(json_extract(vote.value, '$.scores'), '[]'))
+FROM room,
+  json_each(COALESCE(json_extract(room.state, '$.V2'), json_extract(room.state, '$.V1')), '$.votes') AS vote;
+
+-- V3 is V2 without choices and votes. Settings missing from V1 rooms take
+-- their defaults when read.
+UPDATE room SET state = json_object('V3', json_remove(
+  COALESCE(json_extract(state, '$.V2'), json_extract(state, '$.V1')),
+  '$.choices',
+  '$.votes'
+));
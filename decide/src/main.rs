@@ -2,7 +2,6 @@ use std::net::SocketAddr;
 
 use warp::{Filter, Rejection};
 
-mod condorcet;
 mod rps;
 mod vote;
 
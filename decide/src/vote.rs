@@ -1,27 +1,39 @@
 use std::{
     collections::HashMap,
-    sync::Arc,
+    sync::{Arc, Mutex},
     time::{Duration, Instant},
 };
 
 use api::VoteWebsocketQueryParams;
 use futures_util::{SinkExt, StreamExt};
+use itertools::Itertools;
 
-use tokio::sync::{watch, Mutex};
+use tokio::sync::{mpsc, oneshot, watch};
 use uuid::Uuid;
 use warp::{
-    hyper::Uri,
+    http::{header, StatusCode},
     ws::{Message, WebSocket},
     Filter, Reply,
 };
 
 use decide_api as api;
+use decide_core::{
+    condorcet::{
+        borda, copeland, kemeny_young_from_totals, ranked_pairs_from_totals, schulze_from_totals,
+        CondorcetTally,
+    },
+    irv::instant_runoff,
+    robustness::robustness,
+    score::{approval, ranked_ballots, star},
+    stv::single_transferable_vote,
+    tiebreak::{lottery, random_ballot},
+};
 
-use crate::{condorcet::ranked_pairs, WebResult};
+use crate::WebResult;
 
 use self::{
-    db::{Db, DbRoom},
-    util::{ClientId, RoomId},
+    db::{DbRoom, RoomError, Storage},
+    util::{unix_now, AdminToken, ClientId, RoomId},
 };
 
 pub(crate) mod db;
@@ -30,48 +42,101 @@ pub(crate) mod util;
 /// Interval at which inactive rooms are deleted.
 const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
 
+/// Messages handled by a room's task.
+enum RoomCommand {
+    Join {
+        client_id: ClientId,
+        tx: watch::Sender<Option<api::ClientNotification>>,
+        admin_token: Option<AdminToken>,
+        // Receives false if the room does not exist.
+        reply: oneshot::Sender<bool>,
+    },
+    Vote {
+        client_id: ClientId,
+        vote: api::UserVote,
+        reply: oneshot::Sender<Result<(), RoomError>>,
+    },
+    Admin {
+        command: AdminCommand,
+        admin_token: Option<AdminToken>,
+        reply: oneshot::Sender<Result<(), RoomError>>,
+    },
+    /// Sent after a connection closes, to drop its handle.
+    Leave { client_id: ClientId },
+    /// Sent when the room's deadline passes.
+    Close,
+}
+
+/// Commands only the room's admin may use.
+#[derive(Clone, Debug)]
+enum AdminCommand {
+    Tally,
+    Reopen,
+    NewRound,
+    AddCandidate(String),
+    RenameCandidate { candidate: usize, name: String },
+    WithdrawCandidate(usize),
+}
+
+/// Sends commands to a room's task.
+#[derive(Clone)]
+struct RoomHandle {
+    tx: mpsc::UnboundedSender<RoomCommand>,
+}
+
 /// State for a room stored in process memory.
-/// All room changes are synchronized with a mutable reference to this struct.
+/// Each room runs as its own task, which owns this struct and applies the
+/// room's commands one at a time, so rooms don't wait on each other.
 /// Note: the database is the source of truth for the room state.
 struct ServerRoom {
+    room_id: RoomId,
+    db: Arc<dyn Storage>,
     // Each client may have multiple tabs open.
     clients: HashMap<ClientId, Vec<ConnectionHandle>>,
-    // NOTE: the database tally_calculated flag is the source of truth for
-    // whether the results are officially tallied i.e. the vote is done.
-    // This field is only used to avoid re-calculating the results.
-    results_cache: Option<api::CondorcetTally>,
+    // NOTE: the database tallied flag is the source of truth for whether the
+    // results are officially tallied i.e. voting is closed. This field is
+    // only used to avoid re-calculating the results, and is cleared when
+    // voting reopens.
+    results_cache: Option<api::VotingResults>,
+    // Standings shown while voting is open, if the room shows live results.
+    provisional_cache: Option<api::ProvisionalResults>,
 }
 
 impl ServerRoom {
-    fn new() -> Self {
+    fn new(room_id: RoomId, db: Arc<dyn Storage>) -> Self {
         Self {
+            room_id,
+            db,
             clients: HashMap::new(),
             results_cache: None,
+            provisional_cache: None,
         }
     }
 
-    fn add_client(&mut self, client_id: ClientId, handle: ConnectionHandle, db_room: &DbRoom) {
-        self.clients.entry(client_id).or_default().push(handle);
-        self.broadcast_room_state(db_room)
-    }
-
-    fn update_results_cache(&mut self, db_room: &DbRoom) {
-        if db_room.tallied && self.results_cache.is_none() {
-            self.results_cache = Some(calculate_room_tally(&db_room.choices, &db_room.votes));
-        } else if !db_room.tallied && self.results_cache.is_some() {
-            log::error!("Results cache incorrectly populated");
+    async fn update_results_cache(&mut self, db_room: &DbRoom) {
+        if !db_room.tallied {
             self.results_cache = None;
+        } else if let Some(results) = &mut self.results_cache {
+            // Ballots may still arrive after tallying; list them all.
+            let (tallied_room, _) = db_room.without_withdrawn();
+            results.votes = tallied_room.votes.values().cloned().collect();
+        } else {
+            self.results_cache = Some(spawn_tally(db_room, calculate_room_tally).await);
         }
+        self.provisional_cache = if db_room.live_results && !db_room.tallied {
+            Some(spawn_tally(db_room, calculate_provisional_results).await)
+        } else {
+            None
+        };
     }
 
-    fn broadcast_room_state(&mut self, db_room: &DbRoom) {
-        self.update_results_cache(db_room);
+    async fn broadcast_room_state(&mut self, db_room: &DbRoom) {
+        self.update_results_cache(db_room).await;
         for (client_id, client) in self.clients.iter() {
             for handle in client {
-                handle
-                    .tx
-                    .send(Some(self.get_client_notification(client_id, db_room)))
-                    .ok();
+                let notification =
+                    self.get_client_notification(client_id, handle.is_admin, db_room);
+                handle.tx.send(Some(notification)).ok();
             }
         }
     }
@@ -79,12 +144,25 @@ impl ServerRoom {
     fn get_client_notification(
         &self,
         client_id: &ClientId,
+        is_admin: bool,
         db_room: &DbRoom,
     ) -> api::ClientNotification {
         let DbRoom {
             choices,
             votes,
             tallied,
+            method,
+            num_winners: _,
+            tie_break: _,
+            seed: _,
+            totals: _,
+            live_results: _,
+            admin_token_hash: _,
+            closes_at,
+            close_after_ballots,
+            close_when_all_voted,
+            withdrawn,
+            history,
         } = db_room;
         if *tallied != self.results_cache.is_some() {
             log::error!(
@@ -96,35 +174,292 @@ impl ServerRoom {
             status: api::ClientStatus::Connected,
             vote: Some(api::VoteView {
                 choices: choices.clone(),
+                withdrawn: withdrawn.clone(),
+                method: *method,
+                max_score: method.max_score(),
                 your_vote: votes.get(client_id).cloned(),
                 num_votes: votes.len(),
                 num_players: self.clients.len(),
-                results: self.results_cache.as_ref().map(|tally| api::VotingResults {
-                    tally: tally.clone(),
-                    votes: db_room.votes.values().cloned().collect(),
-                }),
+                is_admin,
+                seconds_left: closes_at
+                    .filter(|_| !tallied)
+                    .map(|closes_at| closes_at.saturating_sub(unix_now())),
+                close_after_ballots: *close_after_ballots,
+                close_when_all_voted: *close_when_all_voted,
+                provisional: self.provisional_cache.clone(),
+                results: self.results_cache.clone(),
+                history: history.iter().map(api::PastRound::summary).collect(),
             }),
+            error: None,
+            candidate_error: None,
+        }
+    }
+
+    /// Apply commands until every client has left, then remove the room
+    /// from `rooms`.
+    async fn run(
+        mut self,
+        mut rx: mpsc::UnboundedReceiver<RoomCommand>,
+        rooms: Arc<Mutex<HashMap<RoomId, RoomHandle>>>,
+    ) {
+        loop {
+            let command = match rx.try_recv() {
+                Ok(command) => command,
+                Err(_) if self.clients.is_empty() => {
+                    // Commands are only sent with the map locked, so none
+                    // can arrive between this check and the removal.
+                    let mut rooms = rooms.lock().unwrap();
+                    if !rx.is_empty() {
+                        continue;
+                    }
+                    rooms.remove(&self.room_id);
+                    break;
+                }
+                Err(_) => match rx.recv().await {
+                    Some(command) => command,
+                    None => break,
+                },
+            };
+            match command {
+                RoomCommand::Join {
+                    client_id,
+                    tx,
+                    admin_token,
+                    reply,
+                } => {
+                    let joined = self.add_client(client_id, tx, admin_token).await;
+                    reply.send(joined).ok();
+                }
+                RoomCommand::Vote {
+                    client_id,
+                    vote,
+                    reply,
+                } => {
+                    reply.send(self.submit_vote(client_id, vote).await).ok();
+                }
+                RoomCommand::Admin {
+                    command,
+                    admin_token,
+                    reply,
+                } => {
+                    reply.send(self.admin(command, admin_token).await).ok();
+                }
+                RoomCommand::Leave { client_id } => self.prune_connection_handles(client_id).await,
+                RoomCommand::Close => self.close().await,
+            }
+        }
+        log::debug!("Room {} closed", self.room_id);
+    }
+
+    /// Tell every client the room no longer exists and drop them, so that
+    /// the task exits once its queued commands are handled.
+    fn room_deleted(&mut self) {
+        log::warn!("Room {} was deleted while in use", self.room_id);
+        for handle in self.clients.values().flatten() {
+            let notification = error_notification(RoomError::InvalidRoom);
+            handle.tx.send(Some(notification)).ok();
         }
+        self.clients.clear();
+    }
+
+    async fn add_client(
+        &mut self,
+        client_id: ClientId,
+        tx: watch::Sender<Option<api::ClientNotification>>,
+        admin_token: Option<AdminToken>,
+    ) -> bool {
+        let db_room = match self.db.read_room_state(&self.room_id).await {
+            Some(room) => room,
+            None => {
+                log::error!("client {client_id:?} gave invalid room {}", self.room_id);
+                return false;
+            }
+        };
+        self.db.bump_room_activity(&self.room_id).await;
+        let handle = ConnectionHandle {
+            tx,
+            is_admin: db_room.is_admin(admin_token.as_ref()),
+        };
+        self.clients.entry(client_id).or_default().push(handle);
+        self.broadcast_room_state(&db_room).await;
+        true
     }
 
     async fn submit_vote(
         &mut self,
-        room_id: &RoomId,
         client_id: ClientId,
         vote: api::UserVote,
-        db: &Db,
-    ) {
-        let mut db_room = db.read_room_state(room_id).await.expect("Missing DB room");
-        db_room.votes.insert(client_id, vote);
-        db.write_room_state(room_id, db_room.clone()).await;
-        self.broadcast_room_state(&db_room);
+    ) -> Result<(), RoomError> {
+        let update = self
+            .db
+            .update_room_state(&self.room_id, &mut |db_room| {
+                validate_vote(db_room, &vote)?;
+                db_room.insert_vote(client_id, vote.clone());
+                if self.quorum_reached(db_room) {
+                    db_room.tallied = true;
+                }
+                Ok(())
+            })
+            .await;
+        let Some(update) = update else {
+            self.room_deleted();
+            return Err(RoomError::InvalidRoom);
+        };
+        self.broadcast_room_state(&update?).await;
+        Ok(())
     }
 
-    async fn tally(&mut self, room_id: &RoomId, db: &Db) {
-        let mut db_room = db.read_room_state(room_id).await.expect("Missing DB room");
-        db_room.tallied = true;
-        db.write_room_state(room_id, db_room.clone()).await;
-        self.broadcast_room_state(&db_room);
+    /// Whether the room's ballots are enough to close voting without the
+    /// admin ending it.
+    fn quorum_reached(&self, db_room: &DbRoom) -> bool {
+        let enough_ballots = db_room
+            .close_after_ballots
+            .is_some_and(|quorum| db_room.votes.len() >= quorum);
+        // Ballots from clients who have since left are still in the votes, so
+        // check each connected client rather than comparing counts.
+        let all_voted = db_room.close_when_all_voted
+            && self
+                .clients
+                .keys()
+                .all(|client_id| db_room.votes.contains_key(client_id));
+        enough_ballots || all_voted
+    }
+
+    async fn admin(
+        &mut self,
+        command: AdminCommand,
+        admin_token: Option<AdminToken>,
+    ) -> Result<(), RoomError> {
+        loop {
+            // Tallying can take a while, so a new round is tallied first. The
+            // update only stores the results, and may be retried.
+            let round = match command {
+                AdminCommand::NewRound => Some(self.tally_round(admin_token.as_ref()).await?),
+                _ => None,
+            };
+            let mut stale = false;
+            let update = self
+                .db
+                .update_room_state(&self.room_id, &mut |db_room| {
+                    if !db_room.is_admin(admin_token.as_ref()) {
+                        return Err(RoomError::PermissionDenied);
+                    }
+                    match &command {
+                        AdminCommand::AddCandidate(_)
+                        | AdminCommand::RenameCandidate { .. }
+                        | AdminCommand::WithdrawCandidate(_)
+                            if db_room.tallied =>
+                        {
+                            return Err(api::CandidateError::VotingClosed.into());
+                        }
+                        AdminCommand::AddCandidate(name) => db_room.add_candidate(name)?,
+                        AdminCommand::RenameCandidate { candidate, name } => {
+                            db_room.rename_candidate(*candidate, name)?
+                        }
+                        AdminCommand::WithdrawCandidate(candidate) => {
+                            db_room.withdraw_candidate(*candidate)?
+                        }
+                        AdminCommand::Tally => db_room.tallied = true,
+                        AdminCommand::Reopen => db_room.reopen(),
+                        AdminCommand::NewRound => {
+                            let (tallied_room, round) = round.as_ref().expect("Round not tallied");
+                            // Another writer may have changed the ballots
+                            // since they were tallied.
+                            stale = db_room.choices != tallied_room.choices
+                                || db_room.withdrawn != tallied_room.withdrawn
+                                || db_room.votes != tallied_room.votes;
+                            if !stale {
+                                db_room.new_round(round.clone());
+                            }
+                        }
+                    }
+                    Ok(())
+                })
+                .await;
+            let Some(update) = update else {
+                self.room_deleted();
+                return Err(RoomError::InvalidRoom);
+            };
+            let db_room = update?;
+            if stale {
+                log::warn!(
+                    "Room {} changed while tallying a round; retrying",
+                    self.room_id
+                );
+                continue;
+            }
+            self.broadcast_room_state(&db_room).await;
+            return Ok(());
+        }
+    }
+
+    /// Tally the room's current round, as read before starting a new one.
+    /// Reuses the cached results if voting is closed.
+    async fn tally_round(
+        &mut self,
+        admin_token: Option<&AdminToken>,
+    ) -> Result<(DbRoom, api::PastRound), RoomError> {
+        let Some(db_room) = self.db.read_room_state(&self.room_id).await else {
+            self.room_deleted();
+            return Err(RoomError::InvalidRoom);
+        };
+        if !db_room.is_admin(admin_token) {
+            return Err(RoomError::PermissionDenied);
+        }
+        let results = match &self.results_cache {
+            Some(results) if db_room.tallied => {
+                let (tallied_room, _) = db_room.without_withdrawn();
+                api::VotingResults {
+                    votes: tallied_room.votes.values().cloned().collect(),
+                    ..results.clone()
+                }
+            }
+            _ => spawn_tally(&db_room, calculate_room_tally).await,
+        };
+        let round = api::PastRound {
+            choices: db_room.choices.clone(),
+            results,
+        };
+        Ok((db_room, round))
+    }
+
+    /// End voting if the room's deadline has passed, the same way the admin
+    /// can. The room may have been deleted or tallied in the meantime.
+    async fn close(&mut self) {
+        let update = &mut |db_room: &mut DbRoom| {
+            if db_room
+                .closes_at
+                .is_some_and(|closes_at| closes_at <= unix_now())
+            {
+                db_room.tallied = true;
+            }
+            Ok(())
+        };
+        match self.db.update_room_state(&self.room_id, update).await {
+            Some(Ok(db_room)) => self.broadcast_room_state(&db_room).await,
+            Some(Err(e)) => log::error!("Failed to close room {}: {e:?}", self.room_id),
+            None => self.room_deleted(),
+        }
+    }
+
+    async fn prune_connection_handles(&mut self, client_id: ClientId) {
+        if let Some(client_connections) = self.clients.get_mut(&client_id) {
+            client_connections.retain(|conn| !conn.tx.is_closed());
+            log::debug!(
+                "Room {} has {} connections left",
+                self.room_id,
+                client_connections.len()
+            );
+            if client_connections.is_empty() {
+                self.clients.remove(&client_id);
+            }
+        }
+        if !self.clients.is_empty() {
+            match self.db.read_room_state(&self.room_id).await {
+                Some(db_room) => self.broadcast_room_state(&db_room).await,
+                None => self.room_deleted(),
+            }
+        }
     }
 }
 
@@ -132,116 +467,326 @@ impl ServerRoom {
 struct ConnectionHandle {
     // Note: this option is initially None, but all .changed() values must be Some(_).
     tx: watch::Sender<Option<api::ClientNotification>>,
+    // Whether the connection presented the room's admin token.
+    is_admin: bool,
 }
 
+/// State shared by all connections. The room map is only locked to find or
+/// start a room's task, never while waiting on the database.
 pub struct VoteState {
-    rooms: HashMap<RoomId, ServerRoom>,
-    db: Db,
+    rooms: Arc<Mutex<HashMap<RoomId, RoomHandle>>>,
+    db: Arc<dyn Storage>,
 }
 
-fn calculate_room_tally(
-    choices: &[String],
-    votes: &HashMap<ClientId, api::UserVote>,
-) -> api::CondorcetTally {
-    let num_choices = choices.len();
-    let votes: Vec<Vec<crate::condorcet::VoteItem>> = votes
-        .values()
-        .map(|v| {
-            v.selections
-                .iter()
-                .map(|item| crate::condorcet::VoteItem {
-                    candidate: item.candidate,
-                    rank: item.rank,
-                })
-                .collect()
-        })
+/// Check that a vote only names each of the room's candidates once. Ballots
+/// are otherwise stored as sent, so this must pass before they're counted.
+fn validate_vote(db_room: &DbRoom, vote: &api::UserVote) -> Result<(), api::VoteError> {
+    if vote.name.chars().count() > api::MAX_NAME_LEN {
+        return Err(api::VoteError::NameTooLong);
+    }
+    // Only the half of the ballot the method uses is counted, so the other
+    // half must be empty.
+    let wrong_half_used = match db_room.method.max_score() {
+        Some(_) => !vote.selections.is_empty(),
+        None => !vote.scores.is_empty(),
+    };
+    if wrong_half_used {
+        return Err(api::VoteError::WrongBallotType);
+    }
+    let num_choices = db_room.choices.len();
+    let mut seen = vec![false; num_choices];
+    let candidates = vote
+        .selections
+        .iter()
+        .map(|item| item.candidate)
+        .chain(vote.scores.iter().map(|item| item.candidate));
+    for candidate in candidates {
+        match seen.get_mut(candidate) {
+            None => return Err(api::VoteError::UnknownCandidate(candidate)),
+            Some(true) => return Err(api::VoteError::DuplicateCandidate(candidate)),
+            Some(seen) => *seen = true,
+        }
+    }
+    if let Some(max_score) = db_room.method.max_score() {
+        if let Some(item) = vote.scores.iter().find(|item| item.score > max_score) {
+            return Err(api::VoteError::ScoreTooHigh(item.score));
+        }
+    }
+    Ok(())
+}
+
+fn calculate_room_tally(db_room: &DbRoom) -> api::VotingResults {
+    let (db_room, _) = db_room.without_withdrawn();
+    let db_room = &*db_room;
+    let num_choices = db_room.choices.len();
+    // Sorted so that ballots drawn at random are reproducible.
+    let votes: Vec<&api::UserVote> = db_room
+        .votes
+        .iter()
+        .sorted_by_key(|(client_id, _)| client_id.0)
+        .map(|(_, vote)| vote)
         .collect();
-    let results = ranked_pairs(num_choices, votes);
+    let ballots =
+        || -> Vec<Vec<api::VoteItem>> { votes.iter().map(|v| v.selections.clone()).collect() };
+    let score_ballots =
+        || -> Vec<Vec<api::ScoreItem>> { votes.iter().map(|v| v.scores.clone()).collect() };
+    // Kept up to date as votes arrive, so ranking by it doesn't need the ballots.
+    let totals = || db_room.totals.clone();
+    let mut irv = None;
+    let mut stv = None;
+    let mut score = None;
+    let mut method = db_room.method;
+    let results = match db_room.method {
+        api::TallyMethod::RankedPairs => ranked_pairs_from_totals(totals()),
+        api::TallyMethod::Schulze => schulze_from_totals(totals()),
+        api::TallyMethod::KemenyYoung => match kemeny_young_from_totals(totals()) {
+            Ok(results) => results,
+            Err(e) => {
+                log::warn!("{e}; falling back to ranked pairs");
+                method = api::TallyMethod::RankedPairs;
+                ranked_pairs_from_totals(totals())
+            }
+        },
+        api::TallyMethod::InstantRunoff => {
+            let irv_results = instant_runoff(num_choices, ballots());
+            let tally = CondorcetTally::new(totals(), irv_results.ranks.clone(), vec![]);
+            irv = Some(irv_results);
+            tally
+        }
+        api::TallyMethod::SingleTransferableVote => {
+            let stv_results = single_transferable_vote(num_choices, db_room.num_winners, ballots());
+            let tally = CondorcetTally::new(totals(), stv_results.ranks.clone(), vec![]);
+            stv = Some(stv_results);
+            tally
+        }
+        api::TallyMethod::Approval | api::TallyMethod::Star => {
+            let score_results = if db_room.method == api::TallyMethod::Approval {
+                approval(num_choices, score_ballots())
+            } else {
+                star(num_choices, score_ballots())
+            };
+            let tally = CondorcetTally::new(totals(), score_results.ranks.clone(), vec![]);
+            score = Some(score_results);
+            tally
+        }
+    };
 
-    api::CondorcetTally {
-        ranks: results.ranks,
-        totals: results.totals,
+    let tie_break = match db_room.tie_break {
+        api::TieBreak::None => None,
+        // Multi-winner methods rank all winners together.
+        _ if db_room.method == api::TallyMethod::SingleTransferableVote => None,
+        tie_break => {
+            let num_tied_winners = results.ranks.first().map_or(0, Vec::len);
+            // Rock paper scissors has two players, so bigger ties draw lots.
+            let tie_break = match tie_break {
+                api::TieBreak::RockPaperScissors if num_tied_winners > 2 => api::TieBreak::Lottery,
+                tie_break => tie_break,
+            };
+            Some(api::TieBreakResult {
+                method: tie_break,
+                seed: db_room.seed,
+                order: match tie_break {
+                    api::TieBreak::Lottery => Some(lottery(&results.ranks, db_room.seed)),
+                    api::TieBreak::RandomBallot if db_room.method.max_score().is_some() => {
+                        Some(random_ballot(
+                            &results.ranks,
+                            &ranked_ballots(&score_ballots()),
+                            db_room.seed,
+                        ))
+                    }
+                    api::TieBreak::RandomBallot => {
+                        Some(random_ballot(&results.ranks, &ballots(), db_room.seed))
+                    }
+                    api::TieBreak::None | api::TieBreak::RockPaperScissors => None,
+                },
+                // Each round gets its own game, since the seed stays the same.
+                rps_room: (tie_break == api::TieBreak::RockPaperScissors && num_tied_winners == 2)
+                    .then(|| format!("tiebreak-{:x}-{}", db_room.seed, db_room.history.len())),
+            })
+        }
+    };
+    let auxiliary = db_room
+        .method
+        .max_score()
+        .is_none()
+        .then(|| api::AuxiliaryScores {
+            borda: borda(&results.totals),
+            copeland: copeland(&results.totals),
+        });
+    let robustness = (method == api::TallyMethod::RankedPairs)
+        .then(|| robustness(&results.totals, ballots()))
+        .flatten();
+
+    api::VotingResults {
+        choices: db_room.choices.clone(),
+        method,
+        tally: results,
+        irv,
+        stv,
+        score,
+        auxiliary,
+        robustness,
+        tie_break,
+        votes: db_room.votes.values().cloned().collect(),
+    }
+}
+
+/// Run a tally on the blocking thread pool. Large rooms can take a while, and
+/// would otherwise hold up every other room on the same runtime worker.
+async fn spawn_tally<T: Send + 'static>(db_room: &DbRoom, tally: fn(&DbRoom) -> T) -> T {
+    let db_room = db_room.clone();
+    tokio::task::spawn_blocking(move || tally(&db_room))
+        .await
+        .expect("Tally panicked")
+}
+
+/// Compute the standings while voting is open. Condorcet methods only need the
+/// running pairwise matrix; other methods rank every ballot, but skip the tie
+/// break and analysis of the final tally.
+fn calculate_provisional_results(db_room: &DbRoom) -> api::ProvisionalResults {
+    let full_totals = db_room.totals.clone();
+    let (db_room, remaining) = db_room.without_withdrawn();
+    let db_room = &*db_room;
+    let num_choices = db_room.choices.len();
+    let totals = db_room.totals.clone();
+    // In the same order as the final tally, so the leaders match it.
+    let votes: Vec<&api::UserVote> = db_room
+        .votes
+        .iter()
+        .sorted_by_key(|(client_id, _)| client_id.0)
+        .map(|(_, vote)| vote)
+        .collect();
+    let ballots =
+        || -> Vec<Vec<api::VoteItem>> { votes.iter().map(|v| v.selections.clone()).collect() };
+    let score_ballots =
+        || -> Vec<Vec<api::ScoreItem>> { votes.iter().map(|v| v.scores.clone()).collect() };
+    let ranks = match db_room.method {
+        api::TallyMethod::RankedPairs => ranked_pairs_from_totals(totals.clone()).ranks,
+        api::TallyMethod::Schulze => schulze_from_totals(totals.clone()).ranks,
+        api::TallyMethod::KemenyYoung => match kemeny_young_from_totals(totals.clone()) {
+            Ok(results) => results.ranks,
+            Err(_) => ranked_pairs_from_totals(totals.clone()).ranks,
+        },
+        api::TallyMethod::InstantRunoff => instant_runoff(num_choices, ballots()).ranks,
+        api::TallyMethod::SingleTransferableVote => {
+            single_transferable_vote(num_choices, db_room.num_winners, ballots()).ranks
+        }
+        api::TallyMethod::Approval => approval(num_choices, score_ballots()).ranks,
+        api::TallyMethod::Star => star(num_choices, score_ballots()).ranks,
+    };
+    // Report leaders by their index in the room, which includes withdrawn
+    // candidates.
+    let leaders = ranks.into_iter().next().unwrap_or_default();
+    api::ProvisionalResults {
+        totals: full_totals,
+        leaders: leaders.into_iter().map(|c| remaining[c]).collect(),
     }
 }
 
 impl VoteState {
     async fn init(db_url: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        let db = Db::init(db_url).await?;
+        let db = db::connect(db_url).await?;
         Ok(Self {
-            rooms: HashMap::new(),
+            rooms: Arc::new(Mutex::new(HashMap::new())),
             db,
         })
     }
 
-    async fn create_room(&self, choices: Vec<String>) -> RoomId {
-        self.db.create_room(choices).await
+    async fn create_room(&self, db_room: DbRoom) -> RoomId {
+        self.db.create_room(db_room).await
+    }
+
+    /// Send a command to a room's task, starting the task if needed.
+    fn send(&self, room_id: &RoomId, command: RoomCommand) {
+        let mut rooms = self.rooms.lock().unwrap();
+        // A task only exits on its own after removing itself from the map, so
+        // a closed channel means it panicked. Start it again.
+        if rooms.get(room_id).is_some_and(|room| room.tx.is_closed()) {
+            log::error!("Room {room_id} task exited while in the room map; restarting it");
+            rooms.remove(room_id);
+        }
+        let room = rooms.entry(room_id.clone()).or_insert_with(|| {
+            let (tx, rx) = mpsc::unbounded_channel();
+            let room = ServerRoom::new(room_id.clone(), self.db.clone());
+            tokio::spawn(room.run(rx, self.rooms.clone()));
+            RoomHandle { tx }
+        });
+        // The task can't remove itself from the map while it's locked here,
+        // so this only fails if it panics in the meantime. The command's
+        // reply is then dropped, which the caller reports as an error.
+        if room.tx.send(command).is_err() {
+            log::error!("Room {room_id} task exited while in the room map");
+        }
     }
 
     async fn register_client(
-        &mut self,
+        &self,
         room_id: &RoomId,
         client_id: ClientId,
         tx: watch::Sender<Option<api::ClientNotification>>,
+        admin_token: Option<AdminToken>,
     ) -> bool {
-        let db_room = match self.db.read_room_state(room_id).await {
-            Some(room) => room,
-            None => {
-                log::error!("client {client_id:?} gave invalid room {room_id}");
-                return false;
-            }
-        };
-        self.db.bump_room_activity(room_id).await;
-        let room = self
-            .rooms
-            .entry(room_id.clone())
-            .or_insert_with(ServerRoom::new);
-        room.add_client(client_id, ConnectionHandle { tx }, &db_room);
-        true
+        let (reply, response) = oneshot::channel();
+        self.send(
+            room_id,
+            RoomCommand::Join {
+                client_id,
+                tx,
+                admin_token,
+                reply,
+            },
+        );
+        response.await.unwrap_or(false)
     }
 
-    async fn submit_vote(&mut self, room_id: &RoomId, client_id: ClientId, vote: api::UserVote) {
-        if let Some(room) = self.rooms.get_mut(room_id) {
-            room.submit_vote(room_id, client_id, vote, &self.db).await
-        }
+    async fn submit_vote(
+        &self,
+        room_id: &RoomId,
+        client_id: ClientId,
+        vote: api::UserVote,
+    ) -> Result<(), RoomError> {
+        let (reply, response) = oneshot::channel();
+        self.send(
+            room_id,
+            RoomCommand::Vote {
+                client_id,
+                vote,
+                reply,
+            },
+        );
+        response.await.unwrap_or(Err(RoomError::InvalidRoom))
     }
 
-    async fn tally(&mut self, room_id: &RoomId) {
-        if let Some(room) = self.rooms.get_mut(room_id) {
-            room.tally(room_id, &self.db).await
-        }
+    async fn admin(
+        &self,
+        room_id: &RoomId,
+        command: AdminCommand,
+        admin_token: Option<AdminToken>,
+    ) -> Result<(), RoomError> {
+        let (reply, response) = oneshot::channel();
+        self.send(
+            room_id,
+            RoomCommand::Admin {
+                command,
+                admin_token,
+                reply,
+            },
+        );
+        response.await.unwrap_or(Err(RoomError::InvalidRoom))
     }
 
-    async fn prune_connection_handles(&mut self, room_id: &RoomId, client_id: ClientId) {
-        let mut remove_room = false;
-        if let Some(room) = self.rooms.get_mut(room_id) {
-            if let Some(client_connections) = room.clients.get_mut(&client_id) {
-                client_connections.retain(|conn| !conn.tx.is_closed());
-                log::debug!(
-                    "Room {room_id} has {} connections left",
-                    client_connections.len()
-                );
-                if client_connections.is_empty() {
-                    room.clients.remove(&client_id);
-                }
-            }
-            if room.clients.is_empty() {
-                remove_room = true;
-            }
-            let db_room = self
-                .db
-                .read_room_state(room_id)
-                .await
-                .expect("Missing DB room");
-            room.broadcast_room_state(&db_room);
-        }
-        if remove_room {
-            self.rooms.remove(room_id);
-        }
+    async fn read_room_state(&self, room_id: &RoomId) -> Option<DbRoom> {
+        self.db.read_room_state(room_id).await
     }
 
-    async fn cleanup_rooms(&mut self) {
+    fn prune_connection_handles(&self, room_id: &RoomId, client_id: ClientId) {
+        self.send(room_id, RoomCommand::Leave { client_id });
+    }
+
+    async fn cleanup_rooms(&self) {
         let start = Instant::now();
-        for room_id in self.rooms.keys() {
+        let room_ids: Vec<RoomId> = self.rooms.lock().unwrap().keys().cloned().collect();
+        for room_id in room_ids.iter() {
             self.db.bump_room_activity(room_id).await;
         }
         self.db.cleanup_rooms().await;
@@ -249,24 +794,79 @@ impl VoteState {
     }
 }
 
-async fn start_vote(state: Arc<Mutex<VoteState>>, form: api::NewVoteForm) -> WebResult<impl Reply> {
-    let choices = form
+async fn start_vote(state: Arc<VoteState>, form: api::NewVoteForm) -> WebResult<impl Reply> {
+    let choices: Vec<String> = form
         .choices
         .split('\n')
         .map(|choice| choice.trim())
         .filter(|choice| !choice.is_empty())
         .map(|choice| choice.to_owned())
         .collect();
-    let room_id = state.lock().await.create_room(choices).await;
-    let uri = Uri::builder()
-        .path_and_query(format!("/vote/{room_id}"))
-        .build()
-        .unwrap();
-    Ok(warp::redirect::see_other(uri))
+    let num_winners = form.num_winners.clamp(1, choices.len().max(1));
+    let totals = vec![vec![0; choices.len()]; choices.len()];
+    let admin_token = AdminToken::new_random();
+    let closes_at = (form.closes_in_minutes > 0)
+        .then(|| unix_now().saturating_add(form.closes_in_minutes.saturating_mul(60)));
+    let db_room = DbRoom {
+        choices,
+        votes: HashMap::new(),
+        tallied: false,
+        method: form.method,
+        num_winners,
+        tie_break: form.tie_break,
+        // Small enough to survive being published as a JavaScript number.
+        seed: rand::random_range(0..1 << 53),
+        totals,
+        live_results: form.live_results,
+        admin_token_hash: Some(admin_token.hash()),
+        closes_at,
+        close_after_ballots: (form.close_after_ballots > 0).then_some(form.close_after_ballots),
+        close_when_all_voted: form.close_when_all_voted,
+        withdrawn: vec![],
+        history: vec![],
+    };
+    let room_id = state.create_room(db_room).await;
+    if let Some(closes_at) = closes_at {
+        tokio::spawn(run_deadline_task(state.clone(), room_id.clone(), closes_at));
+    }
+    // Browsers never send the fragment back, so the token stays out of request
+    // logs; the client keeps it and presents it when connecting. `Uri` drops
+    // fragments, so the header is set by hand.
+    let location = format!("/vote/{room_id}#admin={}", admin_token.0);
+    Ok(warp::reply::with_header(
+        warp::reply::with_status(warp::reply(), StatusCode::SEE_OTHER),
+        header::LOCATION,
+        location,
+    ))
+}
+
+/// A room's earlier rounds as JSON, for keeping a record of past results.
+async fn export_history(room_id: String, state: Arc<VoteState>) -> WebResult<impl Reply> {
+    match state.read_room_state(&RoomId(room_id)).await {
+        Some(db_room) => Ok(warp::reply::json(&db_room.history)),
+        None => Err(warp::reject::not_found()),
+    }
+}
+
+/// Tells a client why its command was rejected. Sent only to the connection
+/// that sent the command, which keeps its last view of the room.
+fn error_notification(error: RoomError) -> api::ClientNotification {
+    let (status, error, candidate_error) = match error {
+        RoomError::InvalidRoom => (api::ClientStatus::InvalidRoom, None, None),
+        RoomError::InvalidVote(e) => (api::ClientStatus::InvalidVote, Some(e), None),
+        RoomError::PermissionDenied => (api::ClientStatus::PermissionDenied, None, None),
+        RoomError::InvalidCandidate(e) => (api::ClientStatus::InvalidCandidate, None, Some(e)),
+    };
+    api::ClientNotification {
+        status,
+        vote: None,
+        error,
+        candidate_error,
+    }
 }
 
 async fn handle_vote_client(
-    global_state: Arc<Mutex<VoteState>>,
+    global_state: Arc<VoteState>,
     params: VoteWebsocketQueryParams,
     room_id: String,
     mut ws: WebSocket,
@@ -281,6 +881,8 @@ async fn handle_vote_client(
                 serde_json::to_string(&api::ClientNotification {
                     status: api::ClientStatus::InvalidUuid,
                     vote: None,
+                    error: None,
+                    candidate_error: None,
                 })
                 .unwrap(),
             ))
@@ -289,35 +891,46 @@ async fn handle_vote_client(
             return;
         }
     });
+    let admin_token = params.admin.map(AdminToken);
+    if !global_state
+        .register_client(&room_id, client_id, tx, admin_token.clone())
+        .await
     {
-        let mut gs = global_state.lock().await;
-        if !gs.register_client(&room_id, client_id, tx).await {
-            log::debug!("client {client_id} gave invalid room {room_id}");
-            ws.feed(Message::text(
-                serde_json::to_string(&api::ClientNotification {
-                    status: api::ClientStatus::InvalidRoom,
-                    vote: None,
-                })
-                .unwrap(),
-            ))
-            .await
-            .ok();
-            return;
-        }
-    };
+        log::debug!("client {client_id} gave invalid room {room_id}");
+        ws.feed(Message::text(
+            serde_json::to_string(&api::ClientNotification {
+                status: api::ClientStatus::InvalidRoom,
+                vote: None,
+                error: None,
+                candidate_error: None,
+            })
+            .unwrap(),
+        ))
+        .await
+        .ok();
+        return;
+    }
     log::debug!("client {client_id} connected to room {room_id}");
-    let on_command = |global_state: Arc<Mutex<VoteState>>, room_id, client_id, command| async move {
+    let on_command = |global_state: Arc<VoteState>, room_id, client_id, admin_token, command| async move {
         log::debug!("client {client_id} sent command: {:?}", command);
-        match command {
+        let command = match command {
             api::Command::Vote(user_vote) => {
-                let mut gs = global_state.lock().await;
-                gs.submit_vote(&room_id, client_id, user_vote).await;
+                return global_state
+                    .submit_vote(&room_id, client_id, user_vote)
+                    .await;
             }
-            api::Command::Tally => {
-                let mut gs = global_state.lock().await;
-                gs.tally(&room_id).await;
+            api::Command::Tally => AdminCommand::Tally,
+            api::Command::Reopen => AdminCommand::Reopen,
+            api::Command::NewRound => AdminCommand::NewRound,
+            api::Command::AddCandidate(name) => AdminCommand::AddCandidate(name),
+            api::Command::RenameCandidate { candidate, name } => {
+                AdminCommand::RenameCandidate { candidate, name }
             }
-        }
+            api::Command::WithdrawCandidate(candidate) => {
+                AdminCommand::WithdrawCandidate(candidate)
+            }
+        };
+        global_state.admin(&room_id, command, admin_token).await
     };
     loop {
         tokio::select! {
@@ -353,15 +966,24 @@ async fn handle_vote_client(
                             Ok(command) => {
                                 let command_start = Instant::now();
                                 let command_name = api::Command::name(&command);
-                                on_command(
+                                let result = on_command(
                                     global_state.clone(),
                                     room_id.clone(),
                                     client_id,
+                                    admin_token.clone(),
                                     command
                                 )
                                 .await;
                                 let elapsed = Instant::now() - command_start;
                                 log::info!("{client_id} {command_name} {elapsed:?}");
+                                if let Err(error) = result {
+                                    log::debug!("client {client_id} {command_name} rejected: {error:?}");
+                                    let notification = error_notification(error);
+                                    let serialized_msg = serde_json::to_string(&notification).unwrap();
+                                    if ws.send(Message::text(serialized_msg)).await.is_err() {
+                                        break
+                                    }
+                                }
                             },
                             Err(e) => {
                                 log::debug!("Bad message: {:?}: {:?}", msg, e);
@@ -383,28 +1005,44 @@ async fn handle_vote_client(
     }
     {
         // cleanup
-        let mut gs = global_state.lock().await;
         drop(rx);
-        gs.prune_connection_handles(&room_id, client_id).await;
+        global_state.prune_connection_handles(&room_id, client_id);
         log::debug!("closed connection from client {client_id}");
     }
 }
 
 // Background task that cleans up old rooms.
-async fn run_cleanup_task(global_state: Arc<Mutex<VoteState>>) {
+async fn run_cleanup_task(global_state: Arc<VoteState>) {
     loop {
         tokio::time::sleep(CLEANUP_INTERVAL).await;
-        let mut gs = global_state.lock().await;
-        gs.cleanup_rooms().await;
+        global_state.cleanup_rooms().await;
     }
 }
 
+// Background task that ends voting in a room once its deadline passes.
+async fn run_deadline_task(global_state: Arc<VoteState>, room_id: RoomId, closes_at: u64) {
+    let wait = Duration::from_secs(closes_at.saturating_sub(unix_now()));
+    tokio::time::sleep(wait).await;
+    global_state.send(&room_id, RoomCommand::Close);
+}
+
 #[allow(opaque_hidden_inferred_bound)]
 pub async fn routes(
     db_url: &str,
 ) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    let vote_state = Arc::new(Mutex::new(VoteState::init(db_url).await.unwrap()));
+    let vote_state = Arc::new(VoteState::init(db_url).await.unwrap());
     tokio::spawn(run_cleanup_task(vote_state.clone()));
+    // Deadline tasks don't survive a restart, so start them again.
+    for (room_id, closes_at) in vote_state.db.room_deadlines().await {
+        tokio::spawn(run_deadline_task(vote_state.clone(), room_id, closes_at));
+    }
+    filters(vote_state)
+}
+
+#[allow(opaque_hidden_inferred_bound)]
+fn filters(
+    vote_state: Arc<VoteState>,
+) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     let with_vote_state = warp::any().map(move || vote_state.clone());
     let new_vote_route = warp::path!("api" / "start_vote")
         .and(warp::post())
@@ -412,6 +1050,10 @@ pub async fn routes(
         .and(with_vote_state.clone())
         .and(warp::body::form())
         .and_then(start_vote);
+    let history_route = warp::path!("api" / "vote" / String / "history")
+        .and(warp::get())
+        .and(with_vote_state.clone())
+        .and_then(export_history);
     let vote_route = warp::path!("api" / "vote" / String)
         .and(warp::query::query())
         .and(warp::ws())
@@ -421,5 +1063,439 @@ pub async fn routes(
                 WebResult::Ok(ws.on_upgrade(|ws| handle_vote_client(state, params, room_id, ws)))
             },
         );
-    new_vote_route.or(vote_route)
+    new_vote_route.or(history_route).or(vote_route)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vote::db::test::{room, vote};
+
+    async fn vote_state() -> VoteState {
+        VoteState::init("memory:").await.unwrap()
+    }
+
+    #[test]
+    fn test_validate_vote() {
+        let db_room = room(&["a", "b", "c"]);
+        assert_eq!(validate_vote(&db_room, &vote("alice", &[2, 0, 1])), Ok(()));
+        // Candidates may be left unranked.
+        assert_eq!(validate_vote(&db_room, &vote("alice", &[1])), Ok(()));
+        assert_eq!(
+            validate_vote(&db_room, &vote(&"x".repeat(api::MAX_NAME_LEN + 1), &[0])),
+            Err(api::VoteError::NameTooLong)
+        );
+        assert_eq!(
+            validate_vote(&db_room, &vote("alice", &[0, 3])),
+            Err(api::VoteError::UnknownCandidate(3))
+        );
+        assert_eq!(
+            validate_vote(&db_room, &vote("alice", &[1, 0, 1])),
+            Err(api::VoteError::DuplicateCandidate(1))
+        );
+        let mut mixed = vote("alice", &[0]);
+        mixed.scores = vec![api::ScoreItem {
+            candidate: 0,
+            score: 1,
+        }];
+        assert_eq!(
+            validate_vote(&db_room, &mixed),
+            Err(api::VoteError::WrongBallotType)
+        );
+    }
+
+    #[test]
+    fn test_validate_score_vote() {
+        let db_room = DbRoom {
+            method: api::TallyMethod::Star,
+            ..room(&["a", "b"])
+        };
+        let scores = |scores: &[u64]| api::UserVote {
+            name: "alice".to_owned(),
+            selections: vec![],
+            scores: scores
+                .iter()
+                .enumerate()
+                .map(|(candidate, &score)| api::ScoreItem { candidate, score })
+                .collect(),
+        };
+        assert_eq!(validate_vote(&db_room, &scores(&[5, 0])), Ok(()));
+        assert_eq!(
+            validate_vote(&db_room, &scores(&[6, 0])),
+            Err(api::VoteError::ScoreTooHigh(6))
+        );
+        assert_eq!(
+            validate_vote(&db_room, &scores(&[1, 2, 3])),
+            Err(api::VoteError::UnknownCandidate(2))
+        );
+        assert_eq!(
+            validate_vote(&db_room, &vote("alice", &[0, 1])),
+            Err(api::VoteError::WrongBallotType)
+        );
+    }
+
+    #[test]
+    fn test_provisional_results() {
+        let methods = [
+            api::TallyMethod::InstantRunoff,
+            api::TallyMethod::SingleTransferableVote,
+            api::TallyMethod::Approval,
+            api::TallyMethod::Star,
+        ];
+        for method in methods {
+            let mut db_room = DbRoom {
+                method,
+                ..room(&["a", "b", "c", "d"])
+            };
+            db_room.withdrawn = vec![1];
+            for (i, order) in [[0, 2, 3], [2, 0, 3], [2, 3, 0], [3, 2, 0]]
+                .iter()
+                .enumerate()
+            {
+                let mut ballot = vote("voter", order);
+                if method.max_score().is_some() {
+                    ballot.scores = order
+                        .iter()
+                        .zip([5, 3, 0])
+                        .map(|(&candidate, score)| api::ScoreItem {
+                            candidate,
+                            score: score.min(method.max_score().unwrap()),
+                        })
+                        .collect();
+                    ballot.selections.clear();
+                }
+                db_room.insert_vote(ClientId(Uuid::from_u128(i as u128)), ballot);
+            }
+            let provisional = calculate_provisional_results(&db_room);
+            let results = calculate_room_tally(&db_room);
+            // The final results only list the candidates still running.
+            let remaining = [0, 2, 3];
+            let leaders: Vec<usize> = results.tally.ranks[0]
+                .iter()
+                .map(|&c| remaining[c])
+                .collect();
+            assert_eq!(provisional.leaders, leaders, "{method:?}");
+            assert!(!provisional.leaders.contains(&1));
+        }
+    }
+
+    #[test]
+    fn test_rock_paper_scissors() {
+        let mut db_room = DbRoom {
+            tie_break: api::TieBreak::RockPaperScissors,
+            seed: 0xabc,
+            ..room(&["a", "b", "c"])
+        };
+        db_room.insert_vote(ClientId(Uuid::from_u128(1)), vote("alice", &[0, 1, 2]));
+        db_room.insert_vote(ClientId(Uuid::from_u128(2)), vote("bob", &[1, 0, 2]));
+        let tie_break = calculate_room_tally(&db_room).tie_break.unwrap();
+        assert_eq!(tie_break.method, api::TieBreak::RockPaperScissors);
+        assert_eq!(tie_break.order, None);
+        assert_eq!(tie_break.rps_room.as_deref(), Some("tiebreak-abc-0"));
+
+        // Later rounds play a new game.
+        let round = api::PastRound {
+            choices: db_room.choices.clone(),
+            results: calculate_room_tally(&db_room),
+        };
+        db_room.history.push(round);
+        let tie_break = calculate_room_tally(&db_room).tie_break.unwrap();
+        assert_eq!(tie_break.rps_room.as_deref(), Some("tiebreak-abc-1"));
+
+        // A three-way tie draws lots.
+        db_room.insert_vote(ClientId(Uuid::from_u128(1)), vote("alice", &[0, 1, 2]));
+        db_room.insert_vote(ClientId(Uuid::from_u128(2)), vote("bob", &[1, 2, 0]));
+        db_room.insert_vote(ClientId(Uuid::from_u128(3)), vote("carol", &[2, 0, 1]));
+        let results = calculate_room_tally(&db_room);
+        assert_eq!(results.tally.ranks[0].len(), 3);
+        let tie_break = results.tie_break.unwrap();
+        assert_eq!(tie_break.method, api::TieBreak::Lottery);
+        assert_eq!(tie_break.order.unwrap().len(), 3);
+        assert_eq!(tie_break.rps_room, None);
+    }
+
+    /// A room task's state with the given clients connected.
+    async fn server_room(clients: &[ClientId]) -> ServerRoom {
+        let db = db::connect("memory:").await.unwrap();
+        let mut server_room = ServerRoom::new(RoomId::new_random(), db);
+        for &client_id in clients {
+            let handle = ConnectionHandle {
+                tx: watch::channel(None).0,
+                is_admin: false,
+            };
+            server_room.clients.insert(client_id, vec![handle]);
+        }
+        server_room
+    }
+
+    #[tokio::test]
+    async fn test_quorum_after_ballots() {
+        let alice = ClientId(Uuid::from_u128(1));
+        let bob = ClientId(Uuid::from_u128(2));
+        let server_room = server_room(&[alice, bob]).await;
+        let mut db_room = DbRoom {
+            close_after_ballots: Some(2),
+            ..room(&["a", "b"])
+        };
+        assert!(!server_room.quorum_reached(&db_room));
+        db_room.insert_vote(alice, vote("alice", &[0, 1]));
+        assert!(!server_room.quorum_reached(&db_room));
+        db_room.insert_vote(bob, vote("bob", &[1, 0]));
+        assert!(server_room.quorum_reached(&db_room));
+    }
+
+    #[tokio::test]
+    async fn test_quorum_when_all_voted() {
+        let alice = ClientId(Uuid::from_u128(1));
+        let bob = ClientId(Uuid::from_u128(2));
+        let carol = ClientId(Uuid::from_u128(3));
+        let mut db_room = DbRoom {
+            close_when_all_voted: true,
+            ..room(&["a", "b"])
+        };
+        db_room.insert_vote(alice, vote("alice", &[0, 1]));
+        // Bob voted and then left; the ballot still counts.
+        db_room.insert_vote(bob, vote("bob", &[1, 0]));
+        assert!(server_room(&[alice]).await.quorum_reached(&db_room));
+        // Carol is connected but hasn't voted.
+        let server_room = server_room(&[alice, carol]).await;
+        assert!(!server_room.quorum_reached(&db_room));
+        db_room.insert_vote(carol, vote("carol", &[0, 1]));
+        assert!(server_room.quorum_reached(&db_room));
+        // Without the setting, voting stays open.
+        db_room.close_when_all_voted = false;
+        assert!(!server_room.quorum_reached(&db_room));
+    }
+
+    #[tokio::test]
+    async fn test_admin_token() {
+        let state = vote_state().await;
+        let token = AdminToken("secret".to_owned());
+        let room_id = state
+            .create_room(DbRoom {
+                admin_token_hash: Some(token.hash()),
+                ..room(&["a", "b"])
+            })
+            .await;
+        let wrong_token = Some(AdminToken("guess".to_owned()));
+        for admin_token in [None, wrong_token] {
+            assert_eq!(
+                state
+                    .admin(&room_id, AdminCommand::Tally, admin_token)
+                    .await,
+                Err(RoomError::PermissionDenied)
+            );
+        }
+        assert!(!state.read_room_state(&room_id).await.unwrap().tallied);
+        assert_eq!(
+            state
+                .admin(&room_id, AdminCommand::Tally, Some(token))
+                .await,
+            Ok(())
+        );
+        assert!(state.read_room_state(&room_id).await.unwrap().tallied);
+    }
+
+    #[tokio::test]
+    async fn test_room_without_admin_token() {
+        // Rooms created before admin tokens let everyone act as admin.
+        let state = vote_state().await;
+        let room_id = state.create_room(room(&["a", "b"])).await;
+        assert_eq!(
+            state
+                .admin(&room_id, AdminCommand::AddCandidate("c".to_owned()), None)
+                .await,
+            Ok(())
+        );
+        let guess = Some(AdminToken("guess".to_owned()));
+        assert_eq!(
+            state.admin(&room_id, AdminCommand::Tally, guess).await,
+            Ok(())
+        );
+        let db_room = state.read_room_state(&room_id).await.unwrap();
+        assert_eq!(db_room.choices, ["a", "b", "c"]);
+        assert!(db_room.tallied);
+    }
+
+    #[tokio::test]
+    async fn test_deadline_passed() {
+        // A room whose deadline passed while the server was down closes as
+        // soon as its deadline task starts.
+        let state = Arc::new(vote_state().await);
+        let closes_at = unix_now() - 60;
+        let room_id = state
+            .create_room(DbRoom {
+                closes_at: Some(closes_at),
+                ..room(&["a", "b"])
+            })
+            .await;
+        run_deadline_task(state.clone(), room_id.clone(), closes_at).await;
+        // The room handles commands in order, so it has closed by the time
+        // this client joins.
+        let (tx, rx) = watch::channel(None);
+        let alice = ClientId(Uuid::from_u128(1));
+        assert!(state.register_client(&room_id, alice, tx, None).await);
+        assert!(state.read_room_state(&room_id).await.unwrap().tallied);
+        let notification = rx.borrow().clone().unwrap();
+        let view = notification.vote.unwrap();
+        assert!(view.results.is_some());
+        assert_eq!(view.seconds_left, None);
+    }
+
+    #[tokio::test]
+    async fn test_room_deleted() {
+        let state = vote_state().await;
+        let room_id = state.create_room(room(&["a", "b"])).await;
+        let (tx, rx) = watch::channel(None);
+        let alice = ClientId(Uuid::from_u128(1));
+        assert!(state.register_client(&room_id, alice, tx, None).await);
+        state.db.make_inactive(&room_id).await;
+        state.db.cleanup_rooms().await;
+
+        // The room tells its clients and stops, rather than dropping votes.
+        for _ in 0..2 {
+            assert_eq!(
+                state
+                    .submit_vote(&room_id, alice, vote("alice", &[0, 1]))
+                    .await,
+                Err(RoomError::InvalidRoom)
+            );
+        }
+        let notification = rx.borrow().clone().unwrap();
+        assert!(matches!(
+            notification.status,
+            api::ClientStatus::InvalidRoom
+        ));
+        assert_eq!(
+            state.admin(&room_id, AdminCommand::Tally, None).await,
+            Err(RoomError::InvalidRoom)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_room_task_restarts() {
+        let state = vote_state().await;
+        let room_id = state.create_room(room(&["a", "b"])).await;
+        // A task that exited without removing itself from the map.
+        let (tx, _) = mpsc::unbounded_channel();
+        state
+            .rooms
+            .lock()
+            .unwrap()
+            .insert(room_id.clone(), RoomHandle { tx });
+        let alice = ClientId(Uuid::from_u128(1));
+        assert_eq!(
+            state
+                .submit_vote(&room_id, alice, vote("alice", &[0, 1]))
+                .await,
+            Ok(())
+        );
+        let db_room = state.read_room_state(&room_id).await.unwrap();
+        assert_eq!(db_room.votes.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reopen_after_quorum() {
+        let state = vote_state().await;
+        let room_id = state
+            .create_room(DbRoom {
+                close_after_ballots: Some(1),
+                ..room(&["a", "b"])
+            })
+            .await;
+        let alice = ClientId(Uuid::from_u128(1));
+        state
+            .submit_vote(&room_id, alice, vote("alice", &[0, 1]))
+            .await
+            .unwrap();
+        assert!(state.read_room_state(&room_id).await.unwrap().tallied);
+
+        state
+            .admin(&room_id, AdminCommand::Reopen, None)
+            .await
+            .unwrap();
+        let bob = ClientId(Uuid::from_u128(2));
+        state
+            .submit_vote(&room_id, bob, vote("bob", &[1, 0]))
+            .await
+            .unwrap();
+        let db_room = state.read_room_state(&room_id).await.unwrap();
+        assert!(!db_room.tallied);
+        assert_eq!(db_room.votes.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_new_round() {
+        let state = vote_state().await;
+        let room_id = state
+            .create_room(DbRoom {
+                close_after_ballots: Some(2),
+                ..room(&["a", "b"])
+            })
+            .await;
+        for (n, name, order) in [(1, "alice", [1, 0]), (2, "bob", [1, 0])] {
+            let client_id = ClientId(Uuid::from_u128(n));
+            state
+                .submit_vote(&room_id, client_id, vote(name, &order))
+                .await
+                .unwrap();
+        }
+        assert!(state.read_room_state(&room_id).await.unwrap().tallied);
+
+        state
+            .admin(&room_id, AdminCommand::NewRound, None)
+            .await
+            .unwrap();
+        let db_room = state.read_room_state(&room_id).await.unwrap();
+        assert!(!db_room.tallied);
+        assert!(db_room.votes.is_empty());
+        assert_eq!(db_room.close_after_ballots, Some(2));
+        assert_eq!(db_room.history.len(), 1);
+        let round = &db_room.history[0];
+        assert_eq!(round.choices, ["a", "b"]);
+        assert_eq!(round.results.votes.len(), 2);
+        assert_eq!(round.summary().winners, ["b"]);
+
+        // The new round is tallied on its own.
+        let carol = ClientId(Uuid::from_u128(3));
+        state
+            .submit_vote(&room_id, carol, vote("carol", &[0, 1]))
+            .await
+            .unwrap();
+        let db_room = state.read_room_state(&room_id).await.unwrap();
+        assert!(!db_room.tallied);
+        assert_eq!(db_room.totals, [[0, 1], [0, 0]]);
+    }
+
+    #[tokio::test]
+    async fn test_export_history() {
+        let state = Arc::new(vote_state().await);
+        let room_id = state.create_room(room(&["a", "b"])).await;
+        let alice = ClientId(Uuid::from_u128(1));
+        state
+            .submit_vote(&room_id, alice, vote("alice", &[0, 1]))
+            .await
+            .unwrap();
+        state
+            .admin(&room_id, AdminCommand::NewRound, None)
+            .await
+            .unwrap();
+
+        let routes = filters(state);
+        let response = warp::test::request()
+            .path(&format!("/api/vote/{}/history", room_id.0))
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 200);
+        let history: Vec<api::PastRound> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].choices, ["a", "b"]);
+        assert_eq!(history[0].results.votes, [vote("alice", &[0, 1])]);
+
+        let response = warp::test::request()
+            .path("/api/vote/missing/history")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 404);
+    }
 }
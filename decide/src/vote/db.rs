@@ -1,25 +1,58 @@
-use std::{collections::HashMap, str::FromStr};
+use std::{borrow::Cow, collections::HashMap, str::FromStr, sync::Arc};
 
+use futures_util::future::BoxFuture;
 use serde::{Deserialize, Serialize};
-use sqlx::{
-    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous},
-    Row,
-};
+use uuid::Uuid;
 
 use decide_api as api;
+use decide_core::{
+    condorcet::{add_ballot, remove_ballot},
+    score::ranked_ballot,
+};
 
-use super::util::{ClientId, RoomId};
+use super::util::{AdminToken, ClientId, RoomId};
 
+mod memory;
+mod postgres;
+mod sqlite;
+
+/// Room settings. The choices and votes are stored in the candidate and
+/// ballot tables; V1 and V2 states held them too, until the migration that
+/// added those tables converted every room to V3.
 #[derive(Serialize, Deserialize)]
-struct DbRoomStateV1 {
-    choices: Vec<String>,
-    votes: HashMap<ClientId, api::UserVote>,
+struct DbRoomStateV3 {
     tallied: bool,
+    /// Missing for rooms created before the method could be chosen, which
+    /// used ranked pairs.
+    #[serde(default)]
+    method: api::TallyMethod,
+    #[serde(default = "api::default_num_winners")]
+    num_winners: usize,
+    #[serde(default)]
+    tie_break: api::TieBreak,
+    #[serde(default)]
+    seed: u64,
+    /// Missing for rooms created before totals were kept; recomputed on read.
+    #[serde(default)]
+    totals: Vec<Vec<u64>>,
+    #[serde(default)]
+    live_results: bool,
+    /// Missing for rooms created before admin tokens were issued.
+    #[serde(default)]
+    admin_token_hash: Option<String>,
+    #[serde(default)]
+    closes_at: Option<u64>,
+    #[serde(default)]
+    close_after_ballots: Option<usize>,
+    #[serde(default)]
+    close_when_all_voted: bool,
+    #[serde(default)]
+    withdrawn: Vec<usize>,
 }
 
 #[derive(Serialize, Deserialize)]
 enum DbRoomState {
-    V1(DbRoomStateV1),
+    V3(DbRoomStateV3),
 }
 
 #[derive(Clone)]
@@ -27,116 +60,699 @@ pub(crate) struct DbRoom {
     pub choices: Vec<String>,
     pub votes: HashMap<ClientId, api::UserVote>,
     pub tallied: bool,
+    pub method: api::TallyMethod,
+    pub num_winners: usize,
+    pub tie_break: api::TieBreak,
+    /// Seed for random tie breaks, fixed when the room is created so that
+    /// results are reproducible.
+    pub seed: u64,
+    /// Pairwise matrix of all votes, kept up to date as votes arrive.
+    /// totals[a][b] contains the number of votes ranking candidate a over b.
+    pub totals: Vec<Vec<u64>>,
+    /// Whether provisional standings are shown while voting is open.
+    pub live_results: bool,
+    /// SHA-256 hash of the room's admin token, hex encoded. Rooms without
+    /// one let every client use admin commands.
+    pub admin_token_hash: Option<String>,
+    /// Unix time in seconds at which voting closes on its own.
+    pub closes_at: Option<u64>,
+    /// Voting closes once this many ballots are in.
+    pub close_after_ballots: Option<usize>,
+    /// Voting closes once every connected participant has voted.
+    pub close_when_all_voted: bool,
+    /// Indices of withdrawn candidates, in increasing order. Their names and
+    /// ballot entries are kept so that indices stay stable.
+    pub withdrawn: Vec<usize>,
+    /// Earlier rounds, oldest first. Stored in the round table, where rows
+    /// are only added.
+    pub history: Vec<api::PastRound>,
 }
 
-impl From<DbRoomState> for DbRoom {
-    fn from(db_room_state: DbRoomState) -> Self {
-        match db_room_state {
-            DbRoomState::V1(v1) => Self {
-                choices: v1.choices,
-                votes: v1.votes,
-                tallied: v1.tallied,
+impl DbRoom {
+    /// The ranked ballot a vote contributes to the pairwise matrix.
+    pub fn pairwise_ballot(&self, vote: &api::UserVote) -> Vec<api::VoteItem> {
+        match self.method.max_score() {
+            Some(_) => ranked_ballot(&vote.scores),
+            None => vote.selections.clone(),
+        }
+    }
+
+    /// Record a client's vote, replacing their previous vote if any.
+    pub fn insert_vote(&mut self, client_id: ClientId, vote: api::UserVote) {
+        if let Some(old_vote) = self.votes.get(&client_id) {
+            let old_ballot = self.pairwise_ballot(old_vote);
+            remove_ballot(&mut self.totals, &old_ballot);
+        }
+        let ballot = self.pairwise_ballot(&vote);
+        add_ballot(&mut self.totals, &ballot);
+        self.votes.insert(client_id, vote);
+    }
+
+    /// Whether a client presenting `admin_token` may use admin commands.
+    pub fn is_admin(&self, admin_token: Option<&AdminToken>) -> bool {
+        match (&self.admin_token_hash, admin_token) {
+            (None, _) => true,
+            (Some(hash), Some(token)) => *hash == token.hash(),
+            (Some(_), None) => false,
+        }
+    }
+
+    /// Add a candidate. Existing ballots don't rank it, and count as they
+    /// would for any candidate a ballot leaves out.
+    pub fn add_candidate(&mut self, name: &str) -> Result<(), api::CandidateError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(api::CandidateError::EmptyName);
+        }
+        self.choices.push(name.to_owned());
+        self.recompute_totals();
+        Ok(())
+    }
+
+    pub fn rename_candidate(
+        &mut self,
+        candidate: usize,
+        name: &str,
+    ) -> Result<(), api::CandidateError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(api::CandidateError::EmptyName);
+        }
+        let choice = self
+            .choices
+            .get_mut(candidate)
+            .ok_or(api::CandidateError::UnknownCandidate(candidate))?;
+        *choice = name.to_owned();
+        Ok(())
+    }
+
+    pub fn withdraw_candidate(&mut self, candidate: usize) -> Result<(), api::CandidateError> {
+        if candidate >= self.choices.len() {
+            return Err(api::CandidateError::UnknownCandidate(candidate));
+        }
+        if let Err(idx) = self.withdrawn.binary_search(&candidate) {
+            self.withdrawn.insert(idx, candidate);
+        }
+        Ok(())
+    }
+
+    /// The room as it's tallied: withdrawn candidates are dropped from the
+    /// choices and ballots, and the rest renumbered in order. Returns the
+    /// room's original index of each remaining candidate along with it.
+    pub fn without_withdrawn(&self) -> (Cow<'_, DbRoom>, Vec<usize>) {
+        let remaining: Vec<usize> = (0..self.choices.len())
+            .filter(|c| self.withdrawn.binary_search(c).is_err())
+            .collect();
+        if self.withdrawn.is_empty() {
+            return (Cow::Borrowed(self), remaining);
+        }
+        let mut new_index = vec![None; self.choices.len()];
+        for (new, &old) in remaining.iter().enumerate() {
+            new_index[old] = Some(new);
+        }
+        let votes = self
+            .votes
+            .iter()
+            .map(|(client_id, vote)| {
+                let vote = api::UserVote {
+                    name: vote.name.clone(),
+                    selections: vote
+                        .selections
+                        .iter()
+                        .filter_map(|item| {
+                            Some(api::VoteItem {
+                                candidate: new_index[item.candidate]?,
+                                ..*item
+                            })
+                        })
+                        .collect(),
+                    scores: vote
+                        .scores
+                        .iter()
+                        .filter_map(|item| {
+                            Some(api::ScoreItem {
+                                candidate: new_index[item.candidate]?,
+                                ..*item
+                            })
+                        })
+                        .collect(),
+                };
+                (*client_id, vote)
+            })
+            .collect();
+        let mut db_room = Self {
+            choices: remaining.iter().map(|&c| self.choices[c].clone()).collect(),
+            votes,
+            withdrawn: vec![],
+            ..self.clone()
+        };
+        db_room.recompute_totals();
+        (Cow::Owned(db_room), remaining)
+    }
+
+    /// Open voting again. The deadline has passed or no longer applies, so
+    /// it's dropped.
+    fn open(&mut self) {
+        self.tallied = false;
+        self.closes_at = None;
+    }
+
+    /// Open voting again, keeping the ballots so far. They may already meet
+    /// the quorum, which would close the room on the next ballot, so the
+    /// quorum settings are dropped too.
+    pub fn reopen(&mut self) {
+        self.open();
+        self.close_after_ballots = None;
+        self.close_when_all_voted = false;
+    }
+
+    /// Archive the current round and open voting again with no ballots.
+    pub fn new_round(&mut self, round: api::PastRound) {
+        self.history.push(round);
+        self.votes.clear();
+        self.recompute_totals();
+        self.open();
+    }
+
+    pub fn recompute_totals(&mut self) {
+        let num_choices = self.choices.len();
+        let mut totals = vec![vec![0; num_choices]; num_choices];
+        for vote in self.votes.values() {
+            add_ballot(&mut totals, &self.pairwise_ballot(vote));
+        }
+        self.totals = totals;
+    }
+}
+
+impl DbRoom {
+    fn from_db(
+        db_room_state: DbRoomState,
+        choices: Vec<String>,
+        votes: HashMap<ClientId, api::UserVote>,
+        history: Vec<api::PastRound>,
+    ) -> Self {
+        let mut db_room = match db_room_state {
+            DbRoomState::V3(v3) => Self {
+                choices,
+                votes,
+                tallied: v3.tallied,
+                method: v3.method,
+                num_winners: v3.num_winners,
+                tie_break: v3.tie_break,
+                seed: v3.seed,
+                totals: v3.totals,
+                live_results: v3.live_results,
+                admin_token_hash: v3.admin_token_hash,
+                closes_at: v3.closes_at,
+                close_after_ballots: v3.close_after_ballots,
+                close_when_all_voted: v3.close_when_all_voted,
+                withdrawn: v3.withdrawn,
+                history,
             },
+        };
+        if db_room.totals.len() != db_room.choices.len() {
+            db_room.recompute_totals();
         }
+        db_room
     }
 }
 
-impl From<DbRoom> for DbRoomState {
-    fn from(persistent_room_state: DbRoom) -> Self {
-        Self::V1(DbRoomStateV1 {
-            choices: persistent_room_state.choices,
-            votes: persistent_room_state.votes,
+impl From<&DbRoom> for DbRoomState {
+    fn from(persistent_room_state: &DbRoom) -> Self {
+        Self::V3(DbRoomStateV3 {
             tallied: persistent_room_state.tallied,
+            method: persistent_room_state.method,
+            num_winners: persistent_room_state.num_winners,
+            tie_break: persistent_room_state.tie_break,
+            seed: persistent_room_state.seed,
+            totals: persistent_room_state.totals.clone(),
+            live_results: persistent_room_state.live_results,
+            admin_token_hash: persistent_room_state.admin_token_hash.clone(),
+            closes_at: persistent_room_state.closes_at,
+            close_after_ballots: persistent_room_state.close_after_ballots,
+            close_when_all_voted: persistent_room_state.close_when_all_voted,
+            withdrawn: persistent_room_state.withdrawn.clone(),
         })
     }
 }
 
-/*
-CREATE TABLE room (
-  id TEXT PRIMARY KEY,
-  state JSON,
-  last_active DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
-);
-*/
+impl DbRoom {
+    /// The room without choices, votes or history, to diff a new room against.
+    fn empty(&self) -> Self {
+        Self {
+            choices: vec![],
+            votes: HashMap::new(),
+            history: vec![],
+            ..self.clone()
+        }
+    }
+}
+
+fn room_state_json(room_state: &DbRoom) -> String {
+    serde_json::to_string(&DbRoomState::from(room_state)).expect("Failed to serialize room state")
+}
+
+fn parse_room_state(room_id: &RoomId, room_state_json: String) -> Option<DbRoomState> {
+    match serde_json::from_str::<DbRoomState>(&room_state_json) {
+        Ok(room_state) => Some(room_state),
+        Err(e) => {
+            log::error!("Failed to deserialize room {room_id} state: {e}");
+            None
+        }
+    }
+}
+
+/// Parse a row of the ballot table, logging and skipping it if invalid.
+fn parse_ballot(
+    room_id: &RoomId,
+    client_id: String,
+    name: String,
+    selections: String,
+    scores: String,
+) -> Option<(ClientId, api::UserVote)> {
+    let ballot = (|| -> Result<_, Box<dyn std::error::Error>> {
+        let vote = api::UserVote {
+            name,
+            selections: serde_json::from_str(&selections)?,
+            scores: serde_json::from_str(&scores)?,
+        };
+        Ok((ClientId(Uuid::from_str(&client_id)?), vote))
+    })();
+    match ballot {
+        Ok(ballot) => Some(ballot),
+        Err(e) => {
+            log::error!("Failed to read room {room_id} ballot from {client_id}: {e}");
+            None
+        }
+    }
+}
+
+/// Parse a row of the round table, logging and skipping it if invalid.
+fn parse_round(room_id: &RoomId, round_json: String) -> Option<api::PastRound> {
+    match serde_json::from_str(&round_json) {
+        Ok(round) => Some(round),
+        Err(e) => {
+            log::error!("Failed to read room {room_id} round: {e}");
+            None
+        }
+    }
+}
+
+fn round_json(round: &api::PastRound) -> String {
+    serde_json::to_string(round).expect("Failed to serialize round")
+}
+
+/// Rounds added to the history in `new`. Earlier rounds never change.
+fn new_rounds<'a>(
+    old: &'a DbRoom,
+    new: &'a DbRoom,
+) -> impl Iterator<Item = (usize, &'a api::PastRound)> {
+    new.history.iter().enumerate().skip(old.history.len())
+}
+
+/// Candidates that are new or renamed in `new`.
+fn changed_candidates<'a>(
+    old: &'a DbRoom,
+    new: &'a DbRoom,
+) -> impl Iterator<Item = (usize, &'a String)> {
+    new.choices
+        .iter()
+        .enumerate()
+        .filter(|&(idx, name)| old.choices.get(idx) != Some(name))
+}
+
+/// Votes that are new or changed in `new`.
+fn changed_votes<'a>(
+    old: &'a DbRoom,
+    new: &'a DbRoom,
+) -> impl Iterator<Item = (&'a ClientId, &'a api::UserVote)> {
+    new.votes
+        .iter()
+        .filter(|&(client_id, vote)| old.votes.get(client_id) != Some(vote))
+}
 
-pub(crate) struct Db {
-    db_pool: sqlx::Pool<sqlx::Sqlite>,
+/// Clients whose votes are in `old` but not `new`.
+fn removed_votes<'a>(old: &'a DbRoom, new: &'a DbRoom) -> impl Iterator<Item = &'a ClientId> {
+    old.votes
+        .keys()
+        .filter(|client_id| !new.votes.contains_key(client_id))
 }
 
-impl Db {
-    pub async fn init(db_url: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        let connection_options = SqliteConnectOptions::from_str(db_url)?
-            .create_if_missing(true)
-            .journal_mode(SqliteJournalMode::Wal)
-            .synchronous(SqliteSynchronous::Normal);
-        let db_pool = SqlitePoolOptions::new()
-            .connect_with(connection_options)
-            .await?;
-        sqlx::migrate!("./migrations").run(&db_pool).await?;
-        Ok(Self { db_pool })
+/// Why a change to a room was rejected.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum RoomError {
+    /// The room was deleted, or its task stopped.
+    InvalidRoom,
+    InvalidVote(api::VoteError),
+    PermissionDenied,
+    InvalidCandidate(api::CandidateError),
+}
+
+impl From<api::VoteError> for RoomError {
+    fn from(e: api::VoteError) -> Self {
+        Self::InvalidVote(e)
     }
+}
+
+impl From<api::CandidateError> for RoomError {
+    fn from(e: api::CandidateError) -> Self {
+        Self::InvalidCandidate(e)
+    }
+}
+
+/// Changes a room's state, or rejects the change.
+pub(crate) type RoomUpdate<'a> = &'a mut (dyn FnMut(&mut DbRoom) -> Result<(), RoomError> + Send);
 
-    pub async fn create_room(&self, choices: Vec<String>) -> RoomId {
-        let room_id = RoomId::new_random();
-        let room_state = DbRoomState::V1(DbRoomStateV1 {
-            choices,
+/// None if the room does not exist.
+pub(crate) type UpdateResult = Option<Result<DbRoom, RoomError>>;
+
+/// Where rooms are stored. Implementations are chosen by the database URL's
+/// scheme in `connect`.
+pub(crate) trait Storage: Send + Sync {
+    fn create_room(&self, room_state: DbRoom) -> BoxFuture<'_, RoomId>;
+
+    fn read_room_state<'a>(&'a self, room_id: &'a RoomId) -> BoxFuture<'a, Option<DbRoom>>;
+
+    /// Read a room's state, apply `update` and write the result back
+    /// atomically. The write only succeeds if nothing else changed the room
+    /// since the read, so that concurrent writers can't lose changes;
+    /// otherwise the update is retried on the new state.
+    fn update_room_state<'a>(
+        &'a self,
+        room_id: &'a RoomId,
+        update: RoomUpdate<'a>,
+    ) -> BoxFuture<'a, UpdateResult>;
+
+    fn bump_room_activity<'a>(&'a self, room_id: &'a RoomId) -> BoxFuture<'a, ()>;
+
+    /// Rooms still open for voting that have a deadline, with the deadline.
+    fn room_deadlines(&self) -> BoxFuture<'_, Vec<(RoomId, u64)>>;
+
+    /// Delete rooms inactive for a day, unless their deadline is still to
+    /// come.
+    fn cleanup_rooms(&self) -> BoxFuture<'_, ()>;
+
+    /// Mark a room as last active two days ago, so cleanup may delete it.
+    #[cfg(test)]
+    fn make_inactive<'a>(&'a self, room_id: &'a RoomId) -> BoxFuture<'a, ()>;
+}
+
+/// Connect to the storage for a database URL: `sqlite:`, `postgres:` (or
+/// `postgresql:`), or `memory:` to keep rooms in memory.
+pub async fn connect(db_url: &str) -> Result<Arc<dyn Storage>, Box<dyn std::error::Error>> {
+    let scheme = db_url.split(':').next().unwrap_or_default();
+    Ok(match scheme {
+        "sqlite" => Arc::new(sqlite::SqliteDb::init(db_url).await?),
+        "postgres" | "postgresql" => Arc::new(postgres::PostgresDb::init(db_url).await?),
+        "memory" => Arc::new(memory::MemoryDb::default()),
+        _ => return Err(format!("Unsupported database URL: {db_url}").into()),
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use decide_core::condorcet::ranked_pairs_from_totals;
+
+    use super::*;
+    use crate::vote::util::unix_now;
+
+    /// An open ranked pairs room with no votes and no admin token.
+    pub(crate) fn room(choices: &[&str]) -> DbRoom {
+        DbRoom {
+            choices: choices.iter().map(|&c| c.to_owned()).collect(),
             votes: HashMap::new(),
             tallied: false,
-        });
-        let room_state_json =
-            serde_json::to_string(&room_state).expect("Failed to serialize initial room state");
-        sqlx::query("INSERT INTO room (id, state) VALUES (?, ?)")
-            .bind(&room_id.0)
-            .bind(room_state_json)
-            .execute(&self.db_pool)
-            .await
-            .expect("Failed to insert new room");
-        room_id
+            method: api::TallyMethod::RankedPairs,
+            num_winners: 1,
+            tie_break: api::TieBreak::None,
+            seed: 0,
+            totals: vec![vec![0; choices.len()]; choices.len()],
+            live_results: false,
+            admin_token_hash: None,
+            closes_at: None,
+            close_after_ballots: None,
+            close_when_all_voted: false,
+            withdrawn: vec![],
+            history: vec![],
+        }
     }
 
-    pub async fn read_room_state(&self, room_id: &RoomId) -> Option<DbRoom> {
-        let row = sqlx::query("SELECT state FROM room WHERE id = ?")
-            .bind(&room_id.0)
-            .fetch_one(&self.db_pool)
-            .await
-            .ok()?;
-        let room_state_json: String = row.get(0);
-        match serde_json::from_str::<DbRoomState>(&room_state_json) {
-            Ok(room_state) => Some(room_state.into()),
-            Err(e) => {
-                log::error!("Failed to deserialize room {room_id} state: {e}");
-                None
-            }
+    pub(crate) fn vote(name: &str, order: &[usize]) -> api::UserVote {
+        api::UserVote {
+            name: name.to_owned(),
+            selections: order
+                .iter()
+                .enumerate()
+                .map(|(rank, &candidate)| api::VoteItem {
+                    candidate,
+                    rank: rank as u64,
+                })
+                .collect(),
+            scores: vec![],
         }
     }
 
-    pub async fn write_room_state(&self, room_id: &RoomId, room_state: DbRoom) -> bool {
-        let room_state_json = serde_json::to_string(&DbRoomState::from(room_state))
-            .expect("Failed to serialize room state");
-        // Note: silently fails if room does not exist in database.
-        // Returns false if the room does not exist.
-        sqlx::query("UPDATE room SET state = ?, last_active = CURRENT_TIMESTAMP WHERE id = ?")
-            .bind(room_state_json)
-            .bind(&room_id.0)
-            .execute(&self.db_pool)
+    /// Exercise a storage implementation through the trait.
+    async fn check_storage(db: Arc<dyn Storage>) {
+        let room = DbRoom {
+            choices: vec!["a".to_owned(), "b".to_owned()],
+            votes: HashMap::new(),
+            tallied: false,
+            method: api::TallyMethod::RankedPairs,
+            num_winners: 1,
+            tie_break: api::TieBreak::None,
+            seed: 7,
+            totals: vec![vec![0; 2]; 2],
+            live_results: false,
+            admin_token_hash: Some(AdminToken("secret".to_owned()).hash()),
+            closes_at: Some(1_000),
+            close_after_ballots: Some(3),
+            close_when_all_voted: true,
+            withdrawn: vec![1],
+            history: vec![],
+        };
+        let room_id = db.create_room(room).await;
+        assert!(db.read_room_state(&RoomId::new_random()).await.is_none());
+
+        let alice = ClientId(Uuid::from_u128(1));
+        let bob = ClientId(Uuid::from_u128(2));
+        for (client_id, vote) in [(alice, vote("alice", &[0, 1])), (bob, vote("bob", &[1, 0]))] {
+            let update = &mut |db_room: &mut DbRoom| {
+                db_room.insert_vote(client_id, vote.clone());
+                Ok(())
+            };
+            assert!(matches!(
+                db.update_room_state(&room_id, update).await,
+                Some(Ok(_))
+            ));
+        }
+        // Alice changes her mind, and a rejected update changes nothing.
+        let update = &mut |db_room: &mut DbRoom| {
+            db_room.insert_vote(alice, vote("alice", &[1, 0]));
+            db_room.choices[0] = "A".to_owned();
+            Ok(())
+        };
+        db.update_room_state(&room_id, update).await;
+        let update = &mut |db_room: &mut DbRoom| {
+            db_room.votes.clear();
+            Err(RoomError::PermissionDenied)
+        };
+        assert_eq!(
+            db.update_room_state(&room_id, update).await.unwrap().err(),
+            Some(RoomError::PermissionDenied)
+        );
+
+        let room = db.read_room_state(&room_id).await.unwrap();
+        assert_eq!(room.choices, ["A", "b"]);
+        assert_eq!(room.seed, 7);
+        assert_eq!(room.close_after_ballots, Some(3));
+        assert!(room.close_when_all_voted);
+        assert_eq!(room.withdrawn, [1]);
+        assert_eq!(room.votes.len(), 2);
+        assert_eq!(room.votes[&alice], vote("alice", &[1, 0]));
+        assert_eq!(room.totals, [[0, 0], [2, 0]]);
+        assert!(room.is_admin(Some(&AdminToken("secret".to_owned()))));
+        assert!(!room.is_admin(Some(&AdminToken("guess".to_owned()))));
+        assert!(!room.is_admin(None));
+
+        // Only rooms still open are returned, and the database may hold others.
+        assert!(db
+            .room_deadlines()
             .await
-            .expect("Failed to update room")
-            .rows_affected()
-            == 1
-    }
+            .contains(&(room_id.clone(), 1_000)));
+        let update = &mut |db_room: &mut DbRoom| {
+            db_room.tallied = true;
+            Ok(())
+        };
+        db.update_room_state(&room_id, update).await;
+        assert!(!db
+            .room_deadlines()
+            .await
+            .iter()
+            .any(|(id, _)| *id == room_id));
+
+        // A new round keeps the last one's results and clears the ballots.
+        let round = api::PastRound {
+            choices: room.choices.clone(),
+            results: api::VotingResults {
+                choices: room.choices.clone(),
+                method: api::TallyMethod::RankedPairs,
+                tally: ranked_pairs_from_totals(room.totals.clone()),
+                irv: None,
+                stv: None,
+                score: None,
+                auxiliary: None,
+                robustness: None,
+                tie_break: None,
+                votes: room.votes.values().cloned().collect(),
+            },
+        };
+        let update = &mut |db_room: &mut DbRoom| {
+            db_room.new_round(round.clone());
+            Ok(())
+        };
+        db.update_room_state(&room_id, update).await;
+        let room = db.read_room_state(&room_id).await.unwrap();
+        assert!(!room.tallied);
+        assert!(room.votes.is_empty());
+        assert_eq!(room.totals, [[0, 0], [0, 0]]);
+        assert_eq!(room.closes_at, None);
+        // Unlike reopening, the new round starts empty, so the quorum stays.
+        assert_eq!(room.close_after_ballots, Some(3));
+        assert_eq!(room.history.len(), 1);
+        assert_eq!(room.history[0].choices, ["A", "b"]);
+        assert_eq!(room.history[0].results.votes.len(), 2);
+        assert_eq!(room.history[0].results.tally.ranks, [[1], [0]]);
+
+        db.bump_room_activity(&room_id).await;
+        db.cleanup_rooms().await;
+        assert!(db.read_room_state(&room_id).await.is_some());
 
-    pub async fn bump_room_activity(&self, room_id: &RoomId) {
-        sqlx::query("UPDATE room SET last_active = CURRENT_TIMESTAMP WHERE id = ?")
-            .bind(&room_id.0)
-            .execute(&self.db_pool)
+        // Inactive rooms are deleted unless their deadline is still to come.
+        let open_room = |closes_at| DbRoom {
+            closes_at,
+            ..self::room(&["a", "b"])
+        };
+        let upcoming = db.create_room(open_room(Some(unix_now() + 3_600))).await;
+        let passed = db.create_room(open_room(Some(unix_now() - 3_600))).await;
+        let no_deadline = db.create_room(open_room(None)).await;
+        let deadlines = db.room_deadlines().await;
+        assert!(deadlines.iter().any(|(id, _)| *id == upcoming));
+        assert!(deadlines.iter().any(|(id, _)| *id == passed));
+        assert!(!deadlines.iter().any(|(id, _)| *id == no_deadline));
+        for room_id in [&upcoming, &passed, &no_deadline] {
+            db.make_inactive(room_id).await;
+        }
+        db.cleanup_rooms().await;
+        assert!(db.read_room_state(&upcoming).await.is_some());
+        assert!(db.read_room_state(&passed).await.is_none());
+        assert!(db.read_room_state(&no_deadline).await.is_none());
+        assert!(db.read_room_state(&room_id).await.is_some());
+        // Concurrent updates each see the other's ballot.
+        let carol = ClientId(Uuid::from_u128(3));
+        let dave = ClientId(Uuid::from_u128(4));
+        let concurrent_vote = |client_id: ClientId, name: &'static str| {
+            let db = db.clone();
+            let room_id = room_id.clone();
+            async move {
+                let update = &mut |db_room: &mut DbRoom| {
+                    db_room.insert_vote(client_id, vote(name, &[0, 1]));
+                    Ok(())
+                };
+                db.update_room_state(&room_id, update).await
+            }
+        };
+        let (carol_update, dave_update) = tokio::join!(
+            concurrent_vote(carol, "carol"),
+            concurrent_vote(dave, "dave")
+        );
+        assert!(matches!(carol_update, Some(Ok(_))));
+        assert!(matches!(dave_update, Some(Ok(_))));
+        let room = db.read_room_state(&room_id).await.unwrap();
+        assert_eq!(room.votes.len(), 2);
+        assert!(room.votes.contains_key(&carol) && room.votes.contains_key(&dave));
+        assert_eq!(room.totals, [[0, 2], [0, 0]]);
+
+        let update = &mut |_: &mut DbRoom| Ok(());
+        assert!(db
+            .update_room_state(&RoomId::new_random(), update)
             .await
-            .expect("Failed to update room last active");
+            .is_none());
     }
 
-    pub async fn cleanup_rooms(&self) {
-        sqlx::query("DELETE FROM room WHERE last_active < datetime('now','-1 day')")
-            .execute(&self.db_pool)
+    #[test]
+    fn test_candidate_changes() {
+        let alice = ClientId(Uuid::from_u128(1));
+        let bob = ClientId(Uuid::from_u128(2));
+        let mut room = room(&["a", "b", "c"]);
+        room.insert_vote(alice, vote("alice", &[0, 1, 2]));
+        room.insert_vote(bob, vote("bob", &[2, 1, 0]));
+
+        assert_eq!(
+            room.add_candidate("  "),
+            Err(api::CandidateError::EmptyName)
+        );
+        room.add_candidate(" d ").unwrap();
+        assert_eq!(room.choices[3], "d");
+        // Nobody has ranked the new candidate yet.
+        assert_eq!(room.totals[3], [0, 0, 0, 0]);
+        assert_eq!(room.totals[0], [0, 1, 1, 0]);
+
+        room.rename_candidate(0, "A").unwrap();
+        assert_eq!(
+            room.rename_candidate(4, "e"),
+            Err(api::CandidateError::UnknownCandidate(4))
+        );
+        room.withdraw_candidate(1).unwrap();
+        room.withdraw_candidate(1).unwrap();
+        assert_eq!(room.withdrawn, [1]);
+        assert_eq!(
+            room.withdraw_candidate(4),
+            Err(api::CandidateError::UnknownCandidate(4))
+        );
+
+        // Ballots keep the withdrawn candidate until they're tallied.
+        assert_eq!(room.votes[&alice].selections.len(), 3);
+        let (tallied, remaining) = room.without_withdrawn();
+        assert_eq!(remaining, [0, 2, 3]);
+        assert_eq!(tallied.choices, ["A", "c", "d"]);
+        assert!(tallied.withdrawn.is_empty());
+        let selections = &tallied.votes[&alice].selections;
+        assert_eq!(
+            selections
+                .iter()
+                .map(|item| (item.candidate, item.rank))
+                .collect::<Vec<_>>(),
+            [(0, 0), (1, 2)]
+        );
+        assert_eq!(tallied.totals, [[0, 1, 0], [1, 0, 0], [0, 0, 0]]);
+    }
+
+    #[tokio::test]
+    async fn test_memory() {
+        check_storage(connect("memory:").await.unwrap()).await;
+    }
+
+    #[tokio::test]
+    async fn test_sqlite() {
+        let path =
+            std::env::temp_dir().join(format!("decide-test-{}.sqlite", RoomId::new_random()));
+        let db = connect(&format!("sqlite://{}", path.display()))
             .await
-            .expect("Failed to delete rooms");
+            .unwrap();
+        check_storage(db).await;
+        for suffix in ["", "-shm", "-wal"] {
+            std::fs::remove_file(format!("{}{suffix}", path.display())).ok();
+        }
+    }
+
+    /// Runs against the Postgres database in DECIDE_TEST_POSTGRES_URL. Run it
+    /// with `cargo test -- --ignored`.
+    #[tokio::test]
+    #[ignore = "needs DECIDE_TEST_POSTGRES_URL"]
+    async fn test_postgres() {
+        let db_url = std::env::var("DECIDE_TEST_POSTGRES_URL")
+            .expect("DECIDE_TEST_POSTGRES_URL should be set to a Postgres URL");
+        check_storage(connect(&db_url).await.unwrap()).await;
     }
 }
@@ -0,0 +1,95 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use futures_util::{
+    future::{ready, BoxFuture},
+    FutureExt,
+};
+
+use super::{DbRoom, RoomUpdate, Storage, UpdateResult};
+use crate::vote::util::{unix_now, RoomId};
+
+/// How long a room is kept after its last activity.
+const ROOM_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+struct MemoryRoom {
+    state: DbRoom,
+    /// Unix time in seconds.
+    last_active: u64,
+}
+
+/// Keeps rooms in process memory, so they are lost on restart. Meant for
+/// tests and trying out the server without a database.
+#[derive(Default)]
+pub(crate) struct MemoryDb {
+    rooms: Mutex<HashMap<RoomId, MemoryRoom>>,
+}
+
+impl Storage for MemoryDb {
+    fn create_room(&self, room_state: DbRoom) -> BoxFuture<'_, RoomId> {
+        let room_id = RoomId::new_random();
+        let room = MemoryRoom {
+            state: room_state,
+            last_active: unix_now(),
+        };
+        self.rooms.lock().unwrap().insert(room_id.clone(), room);
+        ready(room_id).boxed()
+    }
+
+    fn read_room_state<'a>(&'a self, room_id: &'a RoomId) -> BoxFuture<'a, Option<DbRoom>> {
+        let rooms = self.rooms.lock().unwrap();
+        ready(rooms.get(room_id).map(|room| room.state.clone())).boxed()
+    }
+
+    fn update_room_state<'a>(
+        &'a self,
+        room_id: &'a RoomId,
+        update: RoomUpdate<'a>,
+    ) -> BoxFuture<'a, UpdateResult> {
+        let mut rooms = self.rooms.lock().unwrap();
+        let result = rooms.get_mut(room_id).map(|room| {
+            let mut room_state = room.state.clone();
+            update(&mut room_state)?;
+            room.state = room_state.clone();
+            room.last_active = unix_now();
+            Ok(room_state)
+        });
+        ready(result).boxed()
+    }
+
+    fn bump_room_activity<'a>(&'a self, room_id: &'a RoomId) -> BoxFuture<'a, ()> {
+        if let Some(room) = self.rooms.lock().unwrap().get_mut(room_id) {
+            room.last_active = unix_now();
+        }
+        ready(()).boxed()
+    }
+
+    fn room_deadlines(&self) -> BoxFuture<'_, Vec<(RoomId, u64)>> {
+        let rooms = self.rooms.lock().unwrap();
+        let deadlines = rooms
+            .iter()
+            .filter(|(_, room)| !room.state.tallied)
+            .filter_map(|(room_id, room)| Some((room_id.clone(), room.state.closes_at?)))
+            .collect();
+        ready(deadlines).boxed()
+    }
+
+    fn cleanup_rooms(&self) -> BoxFuture<'_, ()> {
+        let now = unix_now();
+        self.rooms.lock().unwrap().retain(|_, room| {
+            now.saturating_sub(room.last_active) < ROOM_LIFETIME.as_secs()
+                || room
+                    .state
+                    .closes_at
+                    .is_some_and(|closes_at| closes_at > now)
+        });
+        ready(()).boxed()
+    }
+
+    #[cfg(test)]
+    fn make_inactive<'a>(&'a self, room_id: &'a RoomId) -> BoxFuture<'a, ()> {
+        if let Some(room) = self.rooms.lock().unwrap().get_mut(room_id) {
+            room.last_active = unix_now() - 2 * ROOM_LIFETIME.as_secs();
+        }
+        ready(()).boxed()
+    }
+}
@@ -0,0 +1,280 @@
+use futures_util::{future::BoxFuture, FutureExt};
+use sqlx::{
+    postgres::{PgConnection, PgPoolOptions},
+    Row,
+};
+
+use super::{
+    changed_candidates, changed_votes, new_rounds, parse_ballot, parse_room_state, parse_round,
+    removed_votes, room_state_json, round_json, DbRoom, RoomUpdate, Storage, UpdateResult,
+};
+use crate::vote::util::RoomId;
+
+/// Read a room with its candidates and ballots, along with its version.
+async fn read_room(conn: &mut PgConnection, room_id: &RoomId) -> Option<(DbRoom, i64)> {
+    let row = sqlx::query("SELECT state::text, version FROM room WHERE id = $1")
+        .bind(&room_id.0)
+        .fetch_optional(&mut *conn)
+        .await
+        .expect("Failed to read room")?;
+    let room_state = parse_room_state(room_id, row.get(0))?;
+    let choices = sqlx::query_scalar("SELECT name FROM candidate WHERE room_id = $1 ORDER BY idx")
+        .bind(&room_id.0)
+        .fetch_all(&mut *conn)
+        .await
+        .expect("Failed to read candidates");
+    let votes = sqlx::query(
+        "SELECT client_id, name, selections::text, scores::text FROM ballot WHERE room_id = $1",
+    )
+    .bind(&room_id.0)
+    .fetch_all(&mut *conn)
+    .await
+    .expect("Failed to read ballots")
+    .into_iter()
+    .filter_map(|ballot| {
+        parse_ballot(
+            room_id,
+            ballot.get(0),
+            ballot.get(1),
+            ballot.get(2),
+            ballot.get(3),
+        )
+    })
+    .collect();
+    let history =
+        sqlx::query_scalar("SELECT round::text FROM round WHERE room_id = $1 ORDER BY idx")
+            .bind(&room_id.0)
+            .fetch_all(&mut *conn)
+            .await
+            .expect("Failed to read rounds")
+            .into_iter()
+            .filter_map(|round| parse_round(room_id, round))
+            .collect();
+    Some((
+        DbRoom::from_db(room_state, choices, votes, history),
+        row.get(1),
+    ))
+}
+
+/// Write the candidates and ballots that differ between two versions of a
+/// room, one row each.
+async fn write_changes(conn: &mut PgConnection, room_id: &RoomId, old: &DbRoom, new: &DbRoom) {
+    for (idx, name) in changed_candidates(old, new) {
+        sqlx::query(
+            "INSERT INTO candidate (room_id, idx, name) VALUES ($1, $2, $3)
+            ON CONFLICT (room_id, idx) DO UPDATE SET name = excluded.name",
+        )
+        .bind(&room_id.0)
+        .bind(idx as i64)
+        .bind(name)
+        .execute(&mut *conn)
+        .await
+        .expect("Failed to write candidate");
+    }
+    if old.choices.len() > new.choices.len() {
+        sqlx::query("DELETE FROM candidate WHERE room_id = $1 AND idx >= $2")
+            .bind(&room_id.0)
+            .bind(new.choices.len() as i64)
+            .execute(&mut *conn)
+            .await
+            .expect("Failed to delete candidates");
+    }
+    for (idx, round) in new_rounds(old, new) {
+        sqlx::query("INSERT INTO round (room_id, idx, round) VALUES ($1, $2, $3::jsonb)")
+            .bind(&room_id.0)
+            .bind(idx as i64)
+            .bind(round_json(round))
+            .execute(&mut *conn)
+            .await
+            .expect("Failed to write round");
+    }
+    for (client_id, vote) in changed_votes(old, new) {
+        sqlx::query(
+            "INSERT INTO ballot (room_id, client_id, name, selections, scores)
+            VALUES ($1, $2, $3, $4::jsonb, $5::jsonb)
+            ON CONFLICT (room_id, client_id) DO UPDATE SET name = excluded.name,
+            selections = excluded.selections, scores = excluded.scores, submitted = now()",
+        )
+        .bind(&room_id.0)
+        .bind(client_id.to_string())
+        .bind(&vote.name)
+        .bind(serde_json::to_string(&vote.selections).expect("Failed to serialize ballot"))
+        .bind(serde_json::to_string(&vote.scores).expect("Failed to serialize ballot"))
+        .execute(&mut *conn)
+        .await
+        .expect("Failed to write ballot");
+    }
+    for client_id in removed_votes(old, new) {
+        sqlx::query("DELETE FROM ballot WHERE room_id = $1 AND client_id = $2")
+            .bind(&room_id.0)
+            .bind(client_id.to_string())
+            .execute(&mut *conn)
+            .await
+            .expect("Failed to delete ballot");
+    }
+}
+
+pub(crate) struct PostgresDb {
+    db_pool: sqlx::Pool<sqlx::Postgres>,
+}
+
+impl PostgresDb {
+    pub async fn init(db_url: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let db_pool = PgPoolOptions::new().connect(db_url).await?;
+        sqlx::migrate!("./migrations/postgres")
+            .run(&db_pool)
+            .await?;
+        Ok(Self { db_pool })
+    }
+
+    async fn create_room(&self, room_state: DbRoom) -> RoomId {
+        let room_id = RoomId::new_random();
+        let mut transaction = self
+            .db_pool
+            .begin()
+            .await
+            .expect("Failed to start transaction");
+        sqlx::query("INSERT INTO room (id, state) VALUES ($1, $2::jsonb)")
+            .bind(&room_id.0)
+            .bind(room_state_json(&room_state))
+            .execute(&mut *transaction)
+            .await
+            .expect("Failed to insert new room");
+        write_changes(&mut transaction, &room_id, &room_state.empty(), &room_state).await;
+        transaction
+            .commit()
+            .await
+            .expect("Failed to commit new room");
+        room_id
+    }
+
+    async fn read_room_state(&self, room_id: &RoomId) -> Option<DbRoom> {
+        let mut conn = self
+            .db_pool
+            .acquire()
+            .await
+            .expect("Failed to connect to database");
+        read_room(&mut conn, room_id)
+            .await
+            .map(|(room_state, _)| room_state)
+    }
+
+    async fn update_room_state(&self, room_id: &RoomId, update: RoomUpdate<'_>) -> UpdateResult {
+        loop {
+            // Read without locking. Any write committed after the room row is
+            // read bumps its version, so the update below then matches no row.
+            let mut conn = self
+                .db_pool
+                .acquire()
+                .await
+                .expect("Failed to connect to database");
+            let (old_room_state, version) = read_room(&mut conn, room_id).await?;
+            drop(conn);
+            let mut room_state = old_room_state.clone();
+            if let Err(e) = update(&mut room_state) {
+                return Some(Err(e));
+            }
+            let mut transaction = self
+                .db_pool
+                .begin()
+                .await
+                .expect("Failed to start transaction");
+            let rows_affected = sqlx::query(
+                "UPDATE room SET state = $1::jsonb, version = version + 1, last_active = now()
+                WHERE id = $2 AND version = $3",
+            )
+            .bind(room_state_json(&room_state))
+            .bind(&room_id.0)
+            .bind(version)
+            .execute(&mut *transaction)
+            .await
+            .expect("Failed to update room")
+            .rows_affected();
+            if rows_affected == 1 {
+                write_changes(&mut transaction, room_id, &old_room_state, &room_state).await;
+                transaction
+                    .commit()
+                    .await
+                    .expect("Failed to commit room update");
+                return Some(Ok(room_state));
+            }
+            log::warn!("Room {room_id} changed during update; retrying");
+        }
+    }
+
+    async fn bump_room_activity(&self, room_id: &RoomId) {
+        sqlx::query("UPDATE room SET last_active = now() WHERE id = $1")
+            .bind(&room_id.0)
+            .execute(&self.db_pool)
+            .await
+            .expect("Failed to update room last active");
+    }
+
+    async fn room_deadlines(&self) -> Vec<(RoomId, u64)> {
+        sqlx::query(
+            "SELECT id, (state->'V3'->>'closes_at')::bigint FROM room
+            WHERE state->'V3'->>'closes_at' IS NOT NULL
+            AND NOT (state->'V3'->>'tallied')::boolean",
+        )
+        .fetch_all(&self.db_pool)
+        .await
+        .expect("Failed to read room deadlines")
+        .into_iter()
+        .map(|row| (RoomId(row.get(0)), row.get::<i64, _>(1) as u64))
+        .collect()
+    }
+
+    async fn cleanup_rooms(&self) {
+        sqlx::query(
+            "DELETE FROM room WHERE last_active < now() - interval '1 day'
+            AND coalesce((state->'V3'->>'closes_at')::bigint, 0) < extract(epoch FROM now())",
+        )
+        .execute(&self.db_pool)
+        .await
+        .expect("Failed to delete rooms");
+    }
+
+    #[cfg(test)]
+    async fn make_inactive(&self, room_id: &RoomId) {
+        sqlx::query("UPDATE room SET last_active = now() - interval '2 days' WHERE id = $1")
+            .bind(&room_id.0)
+            .execute(&self.db_pool)
+            .await
+            .expect("Failed to update room last active");
+    }
+}
+
+impl Storage for PostgresDb {
+    fn create_room(&self, room_state: DbRoom) -> BoxFuture<'_, RoomId> {
+        self.create_room(room_state).boxed()
+    }
+
+    fn read_room_state<'a>(&'a self, room_id: &'a RoomId) -> BoxFuture<'a, Option<DbRoom>> {
+        self.read_room_state(room_id).boxed()
+    }
+
+    fn update_room_state<'a>(
+        &'a self,
+        room_id: &'a RoomId,
+        update: RoomUpdate<'a>,
+    ) -> BoxFuture<'a, UpdateResult> {
+        self.update_room_state(room_id, update).boxed()
+    }
+
+    fn bump_room_activity<'a>(&'a self, room_id: &'a RoomId) -> BoxFuture<'a, ()> {
+        self.bump_room_activity(room_id).boxed()
+    }
+
+    fn room_deadlines(&self) -> BoxFuture<'_, Vec<(RoomId, u64)>> {
+        self.room_deadlines().boxed()
+    }
+
+    fn cleanup_rooms(&self) -> BoxFuture<'_, ()> {
+        self.cleanup_rooms().boxed()
+    }
+
+    #[cfg(test)]
+    fn make_inactive<'a>(&'a self, room_id: &'a RoomId) -> BoxFuture<'a, ()> {
+        self.make_inactive(room_id).boxed()
+    }
+}
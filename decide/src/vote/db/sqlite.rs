@@ -0,0 +1,429 @@
+use std::str::FromStr;
+
+use futures_util::{future::BoxFuture, FutureExt};
+use sqlx::{
+    sqlite::{
+        SqliteConnectOptions, SqliteConnection, SqliteJournalMode, SqlitePoolOptions,
+        SqliteSynchronous,
+    },
+    Row,
+};
+
+use super::{
+    changed_candidates, changed_votes, new_rounds, parse_ballot, parse_room_state, parse_round,
+    removed_votes, room_state_json, round_json, DbRoom, RoomUpdate, Storage, UpdateResult,
+};
+use crate::vote::util::RoomId;
+
+/*
+CREATE TABLE room (
+  id TEXT PRIMARY KEY,
+  state JSON,
+  last_active DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  version INTEGER NOT NULL DEFAULT 0,
+);
+CREATE TABLE candidate (
+  room_id TEXT NOT NULL REFERENCES room (id) ON DELETE CASCADE,
+  idx INTEGER NOT NULL,
+  name TEXT NOT NULL,
+  PRIMARY KEY (room_id, idx)
+);
+CREATE TABLE ballot (
+  room_id TEXT NOT NULL REFERENCES room (id) ON DELETE CASCADE,
+  client_id TEXT NOT NULL,
+  name TEXT NOT NULL,
+  selections JSON NOT NULL,
+  scores JSON NOT NULL,
+  submitted DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (room_id, client_id)
+);
+CREATE TABLE round (
+  room_id TEXT NOT NULL REFERENCES room (id) ON DELETE CASCADE,
+  idx INTEGER NOT NULL,
+  round JSON NOT NULL,
+  PRIMARY KEY (room_id, idx)
+);
+*/
+
+/// Read a room with its candidates and ballots, along with its version.
+async fn read_room(conn: &mut SqliteConnection, room_id: &RoomId) -> Option<(DbRoom, i64)> {
+    let row = sqlx::query("SELECT state, version FROM room WHERE id = ?")
+        .bind(&room_id.0)
+        .fetch_optional(&mut *conn)
+        .await
+        .expect("Failed to read room")?;
+    let room_state = parse_room_state(room_id, row.get(0))?;
+    let choices = sqlx::query_scalar("SELECT name FROM candidate WHERE room_id = ? ORDER BY idx")
+        .bind(&room_id.0)
+        .fetch_all(&mut *conn)
+        .await
+        .expect("Failed to read candidates");
+    let votes =
+        sqlx::query("SELECT client_id, name, selections, scores FROM ballot WHERE room_id = ?")
+            .bind(&room_id.0)
+            .fetch_all(&mut *conn)
+            .await
+            .expect("Failed to read ballots")
+            .into_iter()
+            .filter_map(|ballot| {
+                parse_ballot(
+                    room_id,
+                    ballot.get(0),
+                    ballot.get(1),
+                    ballot.get(2),
+                    ballot.get(3),
+                )
+            })
+            .collect();
+    let history = sqlx::query_scalar("SELECT round FROM round WHERE room_id = ? ORDER BY idx")
+        .bind(&room_id.0)
+        .fetch_all(&mut *conn)
+        .await
+        .expect("Failed to read rounds")
+        .into_iter()
+        .filter_map(|round| parse_round(room_id, round))
+        .collect();
+    Some((
+        DbRoom::from_db(room_state, choices, votes, history),
+        row.get(1),
+    ))
+}
+
+/// Write the candidates and ballots that differ between two versions of a
+/// room, one row each.
+async fn write_changes(conn: &mut SqliteConnection, room_id: &RoomId, old: &DbRoom, new: &DbRoom) {
+    for (idx, name) in changed_candidates(old, new) {
+        sqlx::query(
+            "INSERT INTO candidate (room_id, idx, name) VALUES (?, ?, ?)
+            ON CONFLICT (room_id, idx) DO UPDATE SET name = excluded.name",
+        )
+        .bind(&room_id.0)
+        .bind(idx as i64)
+        .bind(name)
+        .execute(&mut *conn)
+        .await
+        .expect("Failed to write candidate");
+    }
+    if old.choices.len() > new.choices.len() {
+        sqlx::query("DELETE FROM candidate WHERE room_id = ? AND idx >= ?")
+            .bind(&room_id.0)
+            .bind(new.choices.len() as i64)
+            .execute(&mut *conn)
+            .await
+            .expect("Failed to delete candidates");
+    }
+    for (idx, round) in new_rounds(old, new) {
+        sqlx::query("INSERT INTO round (room_id, idx, round) VALUES (?, ?, ?)")
+            .bind(&room_id.0)
+            .bind(idx as i64)
+            .bind(round_json(round))
+            .execute(&mut *conn)
+            .await
+            .expect("Failed to write round");
+    }
+    for (client_id, vote) in changed_votes(old, new) {
+        sqlx::query(
+            "INSERT INTO ballot (room_id, client_id, name, selections, scores) VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT (room_id, client_id) DO UPDATE SET name = excluded.name,
+            selections = excluded.selections, scores = excluded.scores,
+            submitted = CURRENT_TIMESTAMP",
+        )
+        .bind(&room_id.0)
+        .bind(client_id.to_string())
+        .bind(&vote.name)
+        .bind(serde_json::to_string(&vote.selections).expect("Failed to serialize ballot"))
+        .bind(serde_json::to_string(&vote.scores).expect("Failed to serialize ballot"))
+        .execute(&mut *conn)
+        .await
+        .expect("Failed to write ballot");
+    }
+    for client_id in removed_votes(old, new) {
+        sqlx::query("DELETE FROM ballot WHERE room_id = ? AND client_id = ?")
+            .bind(&room_id.0)
+            .bind(client_id.to_string())
+            .execute(&mut *conn)
+            .await
+            .expect("Failed to delete ballot");
+    }
+}
+
+pub(crate) struct SqliteDb {
+    db_pool: sqlx::Pool<sqlx::Sqlite>,
+}
+
+impl SqliteDb {
+    pub async fn init(db_url: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let connection_options = SqliteConnectOptions::from_str(db_url)?
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .synchronous(SqliteSynchronous::Normal);
+        let db_pool = SqlitePoolOptions::new()
+            .connect_with(connection_options)
+            .await?;
+        sqlx::migrate!("./migrations/sqlite").run(&db_pool).await?;
+        Ok(Self { db_pool })
+    }
+
+    async fn create_room(&self, room_state: DbRoom) -> RoomId {
+        let room_id = RoomId::new_random();
+        let mut transaction = self
+            .db_pool
+            .begin()
+            .await
+            .expect("Failed to start transaction");
+        sqlx::query("INSERT INTO room (id, state) VALUES (?, ?)")
+            .bind(&room_id.0)
+            .bind(room_state_json(&room_state))
+            .execute(&mut *transaction)
+            .await
+            .expect("Failed to insert new room");
+        write_changes(&mut transaction, &room_id, &room_state.empty(), &room_state).await;
+        transaction
+            .commit()
+            .await
+            .expect("Failed to commit new room");
+        room_id
+    }
+
+    async fn read_room_state(&self, room_id: &RoomId) -> Option<DbRoom> {
+        let mut conn = self
+            .db_pool
+            .acquire()
+            .await
+            .expect("Failed to connect to database");
+        read_room(&mut conn, room_id)
+            .await
+            .map(|(room_state, _)| room_state)
+    }
+
+    async fn update_room_state(&self, room_id: &RoomId, update: RoomUpdate<'_>) -> UpdateResult {
+        loop {
+            // Read without locking. Any write committed after the room row is
+            // read bumps its version, so the update below then matches no row.
+            let mut conn = self
+                .db_pool
+                .acquire()
+                .await
+                .expect("Failed to connect to database");
+            let (old_room_state, version) = read_room(&mut conn, room_id).await?;
+            drop(conn);
+            let mut room_state = old_room_state.clone();
+            if let Err(e) = update(&mut room_state) {
+                return Some(Err(e));
+            }
+            // Take the write lock up front, since the transaction only writes.
+            let mut transaction = self
+                .db_pool
+                .begin_with("BEGIN IMMEDIATE")
+                .await
+                .expect("Failed to start transaction");
+            let rows_affected = sqlx::query(
+                "UPDATE room SET state = ?, version = version + 1, last_active = CURRENT_TIMESTAMP
+                WHERE id = ? AND version = ?",
+            )
+            .bind(room_state_json(&room_state))
+            .bind(&room_id.0)
+            .bind(version)
+            .execute(&mut *transaction)
+            .await
+            .expect("Failed to update room")
+            .rows_affected();
+            if rows_affected == 1 {
+                write_changes(&mut transaction, room_id, &old_room_state, &room_state).await;
+                transaction
+                    .commit()
+                    .await
+                    .expect("Failed to commit room update");
+                return Some(Ok(room_state));
+            }
+            log::warn!("Room {room_id} changed during update; retrying");
+        }
+    }
+
+    async fn bump_room_activity(&self, room_id: &RoomId) {
+        sqlx::query("UPDATE room SET last_active = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(&room_id.0)
+            .execute(&self.db_pool)
+            .await
+            .expect("Failed to update room last active");
+    }
+
+    async fn room_deadlines(&self) -> Vec<(RoomId, u64)> {
+        sqlx::query(
+            "SELECT id, json_extract(state, '$.V3.closes_at') FROM room
+            WHERE json_extract(state, '$.V3.closes_at') IS NOT NULL
+            AND NOT json_extract(state, '$.V3.tallied')",
+        )
+        .fetch_all(&self.db_pool)
+        .await
+        .expect("Failed to read room deadlines")
+        .into_iter()
+        .map(|row| (RoomId(row.get(0)), row.get::<i64, _>(1) as u64))
+        .collect()
+    }
+
+    async fn cleanup_rooms(&self) {
+        sqlx::query(
+            "DELETE FROM room WHERE last_active < datetime('now','-1 day')
+            AND coalesce(json_extract(state, '$.V3.closes_at'), 0) < CAST(strftime('%s','now') AS INTEGER)",
+        )
+        .execute(&self.db_pool)
+        .await
+        .expect("Failed to delete rooms");
+    }
+
+    #[cfg(test)]
+    async fn make_inactive(&self, room_id: &RoomId) {
+        sqlx::query("UPDATE room SET last_active = datetime('now','-2 days') WHERE id = ?")
+            .bind(&room_id.0)
+            .execute(&self.db_pool)
+            .await
+            .expect("Failed to update room last active");
+    }
+}
+
+impl Storage for SqliteDb {
+    fn create_room(&self, room_state: DbRoom) -> BoxFuture<'_, RoomId> {
+        self.create_room(room_state).boxed()
+    }
+
+    fn read_room_state<'a>(&'a self, room_id: &'a RoomId) -> BoxFuture<'a, Option<DbRoom>> {
+        self.read_room_state(room_id).boxed()
+    }
+
+    fn update_room_state<'a>(
+        &'a self,
+        room_id: &'a RoomId,
+        update: RoomUpdate<'a>,
+    ) -> BoxFuture<'a, UpdateResult> {
+        self.update_room_state(room_id, update).boxed()
+    }
+
+    fn bump_room_activity<'a>(&'a self, room_id: &'a RoomId) -> BoxFuture<'a, ()> {
+        self.bump_room_activity(room_id).boxed()
+    }
+
+    fn room_deadlines(&self) -> BoxFuture<'_, Vec<(RoomId, u64)>> {
+        self.room_deadlines().boxed()
+    }
+
+    fn cleanup_rooms(&self) -> BoxFuture<'_, ()> {
+        self.cleanup_rooms().boxed()
+    }
+
+    #[cfg(test)]
+    fn make_inactive<'a>(&'a self, room_id: &'a RoomId) -> BoxFuture<'a, ()> {
+        self.make_inactive(room_id).boxed()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use sqlx::migrate::Migrator;
+    use uuid::Uuid;
+
+    use decide_api as api;
+
+    use super::*;
+    use crate::vote::{
+        db::test::vote,
+        util::{ClientId, RoomId},
+    };
+
+    /// Rooms written before the ballot tables keep their candidates and
+    /// ballots once the migration moves them out of the state JSON.
+    #[tokio::test]
+    async fn test_ballot_tables_migration() {
+        let name = format!("decide-test-{}", RoomId::new_random());
+        let dir = std::env::temp_dir().join(&name);
+        let path = std::env::temp_dir().join(format!("{name}.sqlite"));
+        let db_url = format!("sqlite://{}", path.display());
+
+        // Apply only the migrations from before the ballot tables.
+        std::fs::create_dir(&dir).unwrap();
+        for migration in ["20230415004658_init.sql", "20261017120000_room_version.sql"] {
+            std::fs::copy(
+                format!(
+                    "{}/migrations/sqlite/{migration}",
+                    env!("CARGO_MANIFEST_DIR")
+                ),
+                dir.join(migration),
+            )
+            .unwrap();
+        }
+        let db_pool = SqlitePoolOptions::new()
+            .connect_with(
+                SqliteConnectOptions::from_str(&db_url)
+                    .unwrap()
+                    .create_if_missing(true),
+            )
+            .await
+            .unwrap();
+        Migrator::new(dir.as_path())
+            .await
+            .unwrap()
+            .run(&db_pool)
+            .await
+            .unwrap();
+
+        let alice = Uuid::from_u128(1);
+        let bob = Uuid::from_u128(2);
+        let v1 = serde_json::json!({"V1": {
+            "choices": ["a", "b"],
+            "votes": {alice.to_string(): {
+                "name": "alice",
+                "selections": [{"candidate": 1, "rank": 0}, {"candidate": 0, "rank": 1}],
+            }},
+            "tallied": true,
+        }});
+        let v2 = serde_json::json!({"V2": {
+            "choices": ["x", "y", "z"],
+            "votes": {bob.to_string(): {
+                "name": "bob",
+                "selections": [],
+                "scores": [{"candidate": 2, "score": 5}, {"candidate": 0, "score": 1}],
+            }},
+            "tallied": false,
+            "method": "star",
+            "num_winners": 1,
+            "seed": 9,
+            "live_results": true,
+        }});
+        for (id, state) in [("v1", v1), ("v2", v2)] {
+            sqlx::query("INSERT INTO room (id, state) VALUES (?, ?)")
+                .bind(id)
+                .bind(state.to_string())
+                .execute(&db_pool)
+                .await
+                .unwrap();
+        }
+        db_pool.close().await;
+
+        let db = SqliteDb::init(&db_url).await.unwrap();
+        let room = db.read_room_state(&RoomId("v1".to_owned())).await.unwrap();
+        assert_eq!(room.choices, ["a", "b"]);
+        assert!(room.tallied);
+        assert_eq!(room.method, api::TallyMethod::RankedPairs);
+        assert_eq!(room.votes.len(), 1);
+        assert_eq!(room.votes[&ClientId(alice)], vote("alice", &[1, 0]));
+        assert_eq!(room.totals, [[0, 0], [1, 0]]);
+
+        let room = db.read_room_state(&RoomId("v2".to_owned())).await.unwrap();
+        assert_eq!(room.choices, ["x", "y", "z"]);
+        assert!(!room.tallied);
+        assert_eq!(room.method, api::TallyMethod::Star);
+        assert_eq!(room.seed, 9);
+        assert!(room.live_results);
+        let ballot = &room.votes[&ClientId(bob)];
+        assert_eq!(ballot.name, "bob");
+        assert_eq!(ballot.scores.len(), 2);
+        assert_eq!(ballot.scores[0].candidate, 2);
+        assert_eq!(ballot.scores[0].score, 5);
+
+        db.db_pool.close().await;
+        std::fs::remove_dir_all(&dir).ok();
+        for suffix in ["", "-shm", "-wal"] {
+            std::fs::remove_file(format!("{}{suffix}", path.display())).ok();
+        }
+    }
+}
@@ -1,7 +1,11 @@
-use std::str::FromStr;
+use std::{
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use rand::distr::SampleString;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
 /// Each websocket connection is a unique player.
@@ -47,3 +51,26 @@ impl std::fmt::Display for RoomId {
         self.0.fmt(f)
     }
 }
+
+/// Secret given to a room's creator, allowing admin commands. Only its hash
+/// is stored.
+#[derive(Clone)]
+pub struct AdminToken(pub String);
+
+impl AdminToken {
+    pub fn new_random() -> Self {
+        Self(rand::distr::Alphanumeric.sample_string(&mut rand::rng(), 32))
+    }
+
+    pub fn hash(&self) -> String {
+        hex::encode(Sha256::digest(self.0.as_bytes()))
+    }
+}
+
+/// Current Unix time in seconds, as stored for room deadlines.
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System clock is before 1970")
+        .as_secs()
+}
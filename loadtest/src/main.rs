@@ -16,12 +16,18 @@ use decide_api as api;
 
 type ClientStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
 
-async fn make_vote(client: &mut reqwest::Client, base_url: &str, secure: bool) -> String {
+/// Returns the new room's ID and its admin token.
+async fn make_vote(client: &mut reqwest::Client, base_url: &str, secure: bool) -> (String, String) {
     let proto = if secure { "https" } else { "http" };
     let url = format!("{proto}://{base_url}/api/start_vote");
     let body = "choices=a%0D%0Ab%0D%0Ac";
     let response = client.post(url).body(body).send().await.unwrap();
-    response.url().path().split('/').last().unwrap().into()
+    // The redirect is to /vote/{room_id}#admin={token}.
+    let location = response.headers()[reqwest::header::LOCATION]
+        .to_str()
+        .unwrap();
+    let (path, admin_token) = location.split_once("#admin=").unwrap();
+    (path.split('/').last().unwrap().into(), admin_token.into())
 }
 
 struct VoteClient {
@@ -100,7 +106,11 @@ async fn client_work(
                                 .collect();
                             let name = String::from(
                                 *["Fred", "Joe", "???"].choose(&mut rng).unwrap());
-                            api::Command::Vote(api::UserVote{name, selections: vote})
+                            api::Command::Vote(api::UserVote {
+                                name,
+                                selections: vote,
+                                scores: vec![],
+                            })
                         };
                         let command = serde_json::to_string(&command)
                             .expect("Failed to serialize command");
@@ -152,10 +162,11 @@ async fn client_work(
 }
 
 impl VoteClient {
-    async fn connect(base_url: &str, vote_id: &str, secure: bool) -> Self {
+    async fn connect(base_url: &str, vote_id: &str, admin_token: &str, secure: bool) -> Self {
         let proto = if secure { "wss" } else { "ws" };
         let client_id = Uuid::new_v4().to_string();
-        let url = format!("{proto}://{base_url}/api/vote/{vote_id}?id={client_id}");
+        let url =
+            format!("{proto}://{base_url}/api/vote/{vote_id}?id={client_id}&admin={admin_token}");
         let mut ws = tokio_tungstenite::connect_async(url).await.unwrap().0;
         let (tx, rx) = tokio::sync::mpsc::channel(1);
         // wait on initial sync
@@ -192,7 +203,11 @@ async fn main() {
     let num_clients_per_room = 10;
 
     // Create rooms.
-    let mut http_client = reqwest::Client::new();
+    // Don't follow the redirect; the admin token is only in its Location.
+    let mut http_client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
     let mut rooms = vec![];
     for _ in 0..num_rooms {
         rooms.push(make_vote(&mut http_client, &base_url, secure).await);
@@ -206,11 +221,15 @@ async fn main() {
 
     // Create and connect clients to each room.
     let mut client_futs = vec![];
-    for room in rooms {
+    // Every client may end the vote, so they all connect as admins.
+    for (room, admin_token) in rooms {
         for _ in 0..num_clients_per_room {
             let base_url1 = base_url.clone();
             let room1 = room.clone();
-            client_futs.push(async move { VoteClient::connect(&base_url1, &room1, secure).await });
+            let admin_token1 = admin_token.clone();
+            client_futs.push(async move {
+                VoteClient::connect(&base_url1, &room1, &admin_token1, secure).await
+            });
         }
     }
     let clients = futures::future::join_all(client_futs).await;